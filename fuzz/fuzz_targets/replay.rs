@@ -0,0 +1,35 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use weighted_rs::{
+    fuzzing::{Op, Replay},
+    RandWeight, RoundrobinWeight, SmoothWeight,
+};
+
+fn decode(data: &[u8]) -> Vec<Op<u8>> {
+    data.chunks_exact(3)
+        .map(|chunk| {
+            let item = chunk[1] % 8;
+            let weight = chunk[2] as i8 as isize;
+            match chunk[0] % 5 {
+                0 => Op::Add(item, weight),
+                1 => Op::Remove(item),
+                2 => Op::Update(item, weight),
+                3 => Op::Next,
+                _ => Op::Reset,
+            }
+        })
+        .collect()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut sw: SmoothWeight<u8> = SmoothWeight::new();
+    let mut rrw: RoundrobinWeight<u8> = RoundrobinWeight::new();
+    let mut rw: RandWeight<u8> = RandWeight::new();
+
+    for op in decode(data) {
+        sw.apply(op.clone());
+        rrw.apply(op.clone());
+        rw.apply(op);
+    }
+});
@@ -0,0 +1,206 @@
+use super::Weight;
+use rand::prelude::{Rng, ThreadRng};
+
+#[derive(Clone, Debug)]
+struct AliasWeightItem<T> {
+    item: T,
+    weight: isize,
+}
+
+/// AliasWeight selects items at random in proportion to their weights in O(1) per pick, using
+/// Walker's alias method (in Vose's formulation).
+///
+/// The alias table takes O(n) to build. It is rebuilt lazily by the first `next()` after the
+/// items change, so a burst of `add` calls only pays for one build. Items with a non-positive
+/// weight are never selected.
+#[derive(Debug, Default)]
+pub struct AliasWeight<T, R = ThreadRng> {
+    items: Vec<AliasWeightItem<T>>,
+    prob: Vec<f64>,
+    alias: Vec<usize>,
+    dirty: bool,
+    r: R,
+}
+
+impl<T: Clone> AliasWeight<T> {
+    pub fn new() -> Self {
+        AliasWeight::with_rng(rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> AliasWeight<T, R> {
+    /// creates a balancer that draws from `rng`.
+    pub fn with_rng(rng: R) -> Self {
+        AliasWeight {
+            items: Vec::new(),
+            prob: Vec::new(),
+            alias: Vec::new(),
+            dirty: false,
+            r: rng,
+        }
+    }
+
+    fn build(&mut self) {
+        self.dirty = false;
+        self.prob.clear();
+        self.alias.clear();
+
+        let total: i128 = self.items.iter().map(|w| w.weight.max(0) as i128).sum();
+        if total <= 0 {
+            return;
+        }
+
+        let n = self.items.len();
+        self.prob = self
+            .items
+            .iter()
+            .map(|w| w.weight.max(0) as f64 * n as f64 / total as f64)
+            .collect();
+        self.alias = (0..n).collect();
+
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| self.prob[i] < 1.0);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            self.alias[s] = l;
+            self.prob[l] -= 1.0 - self.prob[s];
+            if self.prob[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+
+        // whatever is left over is 1.0 up to rounding, except items that must never be selected
+        let fallback = self.items.iter().position(|w| w.weight > 0).unwrap_or(0);
+        for i in large.into_iter().chain(small) {
+            if self.items[i].weight > 0 {
+                self.prob[i] = 1.0;
+            } else {
+                self.prob[i] = 0.0;
+                self.alias[i] = fallback;
+            }
+        }
+    }
+}
+
+impl<T: Clone, R: Rng> Weight for AliasWeight<T, R> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(AliasWeightItem { item, weight });
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        self.dirty = true;
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.weight = weight;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+        self.prob = Vec::new();
+        self.alias = Vec::new();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+        self.dirty = true;
+    }
+
+    fn reset(&mut self) {}
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for AliasWeight<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.dirty {
+            self.build();
+        }
+        if self.prob.is_empty() {
+            return None;
+        }
+
+        let i = self.r.gen_range(0..self.prob.len());
+        let index = if self.r.gen::<f64>() < self.prob[i] {
+            i
+        } else {
+            self.alias[i]
+        };
+        Some(self.items[index].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AliasWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_alias_weight() {
+        let mut aw: AliasWeight<&str> = AliasWeight::new();
+        assert_eq!(aw.next(), None);
+
+        aw.add("server1", 5);
+        aw.add("server2", 2);
+        aw.add("server3", 3);
+        aw.add("server4", 0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100_000 {
+            *results.entry(aw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!(results["server1"] > 48_500 && results["server1"] < 51_500);
+        assert!(results["server2"] > 19_000 && results["server2"] < 21_000);
+        assert!(results["server3"] > 29_000 && results["server3"] < 31_000);
+        assert_eq!(results.get("server4"), None);
+
+        aw.remove(&"server1");
+        for _ in 0..1000 {
+            assert_ne!(aw.next(), Some("server1"));
+        }
+    }
+}
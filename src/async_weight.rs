@@ -0,0 +1,19 @@
+use core::future::Future;
+
+/// AsyncWeight is the async-friendly counterpart of `Weight`, for a balancer that is shared
+/// between tasks and whose items are replaced from elsewhere, e.g. by a service discovery task.
+///
+/// Selection takes `&self`, so one balancer can serve many tasks without a lock of the caller's
+/// own, and both methods return `Send` futures, so they can be awaited in tasks spawned on a
+/// multi-threaded runtime.
+pub trait AsyncWeight {
+    /// the type of the selected items.
+    type Item;
+
+    /// selects the next item, or returns `None` if nothing can be selected.
+    fn next(&self) -> impl Future<Output = Option<Self::Item>> + Send;
+
+    /// waits until the items are replaced and applies the change. Returns false once no more
+    /// changes can arrive, after which the balancer keeps the items it has.
+    fn changed(&self) -> impl Future<Output = bool> + Send;
+}
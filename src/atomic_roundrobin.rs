@@ -0,0 +1,119 @@
+use super::{RoundrobinWeight, Weight};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+/// AtomicRoundrobin is a round-robin selector that can be shared between threads and selected
+/// from through `&self`.
+///
+/// As long as every item has the same positive weight, round robin is just a rotating cursor, so
+/// `next()` only bumps an `AtomicUsize` and never blocks. Once items with different weights are
+/// added it moves them all into a `RoundrobinWeight` behind a `Mutex`, and every `next()` takes
+/// that lock from then on: there is no way back to the lock-free path.
+///
+/// Items can only be added. There is no `remove` or `update_weight`; build a new selector to
+/// change the items.
+#[derive(Debug, Default)]
+pub struct AtomicRoundrobin<T> {
+    items: Vec<(T, isize)>,
+    cursor: AtomicUsize,
+    locked: Option<Mutex<RoundrobinWeight<T>>>,
+}
+
+impl<T: Clone> AtomicRoundrobin<T> {
+    pub const fn new() -> Self {
+        AtomicRoundrobin {
+            items: Vec::new(),
+            cursor: AtomicUsize::new(0),
+            locked: None,
+        }
+    }
+
+    /// adds a weighted item for selection.
+    pub fn add(&mut self, item: T, weight: isize) {
+        if let Some(locked) = &mut self.locked {
+            locked
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .add(item, weight);
+        } else if self.items.first().is_some_and(|(_, w)| *w != weight) {
+            // the items move over, so the lock-free list is left empty for good
+            let mut rrw: RoundrobinWeight<T> = self.items.drain(..).collect();
+            rrw.add(item, weight);
+            self.items.shrink_to_fit();
+            self.locked = Some(Mutex::new(rrw));
+        } else {
+            self.items.push((item, weight));
+        }
+    }
+
+    /// returns true while selection is served by the lock-free path.
+    pub fn is_lock_free(&self) -> bool {
+        self.locked.is_none()
+    }
+
+    /// selects the next item, taking a lock only if the weights are unequal.
+    pub fn next(&self) -> Option<T> {
+        if let Some(locked) = &self.locked {
+            return locked.lock().unwrap_or_else(|e| e.into_inner()).next();
+        }
+
+        match self.items.first() {
+            Some((_, weight)) if *weight > 0 => {
+                let i = self.cursor.fetch_add(1, Ordering::Relaxed) % self.items.len();
+                Some(self.items[i].0.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::AtomicRoundrobin;
+    use std::{collections::HashMap, sync::Arc, thread};
+
+    #[test]
+    fn test_atomic_roundrobin() {
+        let mut arr: AtomicRoundrobin<&str> = AtomicRoundrobin::new();
+        arr.add("server1", 1);
+        arr.add("server2", 1);
+        arr.add("server3", 1);
+        arr.add("server4", 1);
+        assert!(arr.is_lock_free());
+
+        let arr = Arc::new(arr);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let arr = Arc::clone(&arr);
+                thread::spawn(move || (0..1000).map(|_| arr.next().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for handle in handles {
+            for s in handle.join().unwrap() {
+                *results.entry(s).or_insert(0) += 1;
+            }
+        }
+        for s in ["server1", "server2", "server3", "server4"].iter() {
+            assert_eq!(results[s], 1000);
+        }
+
+        let mut arr: AtomicRoundrobin<&str> = AtomicRoundrobin::new();
+        arr.add("server1", 5);
+        arr.add("server2", 2);
+        arr.add("server3", 3);
+        assert!(!arr.is_lock_free());
+        assert!(arr.items.is_empty());
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(arr.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server2"], 20);
+        assert_eq!(results["server3"], 30);
+    }
+}
@@ -0,0 +1,186 @@
+use super::Weight;
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug)]
+struct BlockItem<T> {
+    item: T,
+    weight: isize,
+}
+
+/// BlockRoundrobin is the classic weighted round-robin that hands out each item `weight` times in
+/// a row before moving on to the next one, e.g. for batch scheduling where every cycle must hold
+/// exactly `weight` copies of each item.
+///
+/// Unlike `RoundrobinWeight` and `SmoothWeight`, which spread an item's selections over the cycle,
+/// this emits contiguous blocks in insertion order: weights `{ a: 3, b: 1, c: 2 }` give
+/// `(a, a, a, b, c, c)` and then start over. Items with a non-positive weight are never selected.
+///
+/// Changing the items mid-cycle carries on from the current position: lowering the weight of the
+/// item being handed out below what it already got ends its block, and removing it moves on to
+/// the next item.
+#[derive(Clone, Debug)]
+pub struct BlockRoundrobin<T> {
+    items: Vec<BlockItem<T>>,
+    i: usize,
+    emitted: isize,
+}
+
+impl<T> Default for BlockRoundrobin<T> {
+    fn default() -> Self {
+        BlockRoundrobin {
+            items: Vec::new(),
+            i: 0,
+            emitted: 0,
+        }
+    }
+}
+
+impl<T: Clone> BlockRoundrobin<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns the number of selections in a full cycle, i.e. the sum of the positive weights,
+    /// saturating at `usize::MAX`.
+    pub fn cycle_length(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|w| w.weight > 0)
+            .fold(0usize, |sum, w| sum.saturating_add(w.weight as usize))
+    }
+}
+
+impl<T: Clone> Weight for BlockRoundrobin<T> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(BlockItem { item, weight });
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        if index < self.i {
+            self.i -= 1;
+        } else if index == self.i {
+            // the next item takes this slot, and starts its block from scratch
+            self.emitted = 0;
+        }
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.items.clear();
+        self.items.shrink_to_fit();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.i = 0;
+        self.emitted = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> Iterator for BlockRoundrobin<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // without an item with a weight the cursor would spin forever
+        if !self.items.iter().any(|w| w.weight > 0) {
+            return None;
+        }
+
+        loop {
+            if self.i >= self.items.len() {
+                self.i = 0;
+                self.emitted = 0;
+            }
+
+            let w = &self.items[self.i];
+            if self.emitted < w.weight {
+                self.emitted += 1;
+                return Some(w.item.clone());
+            }
+            self.i += 1;
+            self.emitted = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BlockRoundrobin, Weight};
+
+    #[test]
+    fn test_block_roundrobin() {
+        let mut bw: BlockRoundrobin<&str> = BlockRoundrobin::new();
+        assert_eq!(bw.next(), None);
+        assert_eq!(bw.cycle_length(), 0);
+
+        bw.add("server1", 3);
+        bw.add("server2", 1);
+        bw.add("server3", 0);
+        bw.add("server4", 2);
+        assert_eq!(bw.cycle_length(), 6);
+        let cycle = vec![
+            "server1", "server1", "server1", "server2", "server4", "server4",
+        ];
+        assert_eq!(bw.next_n(6), cycle);
+        assert_eq!(bw.next_n(6), cycle);
+
+        // lowering the weight of the current block ends it
+        bw.next_n(2);
+        bw.update_weight(&"server1", 1);
+        assert_eq!(bw.next_n(3), vec!["server2", "server4", "server4"]);
+
+        // removing the current item moves on to the next one
+        bw.next();
+        assert_eq!(bw.remove(&"server1"), Some(("server1", 1)));
+        assert_eq!(
+            bw.next_n(4),
+            vec!["server2", "server4", "server4", "server2"]
+        );
+
+        bw.remove_all();
+        assert_eq!(bw.next(), None);
+    }
+}
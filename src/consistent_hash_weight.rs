@@ -0,0 +1,258 @@
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+// how many ring points each unit of weight gets by default
+const DEFAULT_POINTS_PER_WEIGHT: usize = 40;
+
+// the most ring points a single item gets, so a large weight can't exhaust memory
+const MAX_POINTS_PER_ITEM: usize = 1 << 16;
+
+// FNV-1a with a final avalanche step, so the ring doesn't move when the standard library's
+// hasher changes and nearby inputs still land far apart. Integers are hashed as little-endian
+// bytes and `usize`/`isize` as 64 bits, so the ring is the same on every target.
+struct RingHasher(u64);
+
+impl RingHasher {
+    fn new() -> Self {
+        RingHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for RingHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut h = self.0;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        h ^ (h >> 33)
+    }
+}
+
+fn hash_of<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = RingHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone, Debug)]
+struct ConsistentHashItem<T> {
+    item: T,
+    weight: isize,
+}
+
+/// ConsistentHashWeight maps keys to items on a hash ring, so the same key keeps going to the same
+/// item, e.g. for cache affinity.
+///
+/// Each item is placed on the ring at `weight * points_per_weight` points derived from the item
+/// itself, and a key goes to the item owning the first point at or after the key's hash. The share
+/// of keys an item gets follows its weight, and since an item's points don't depend on the other
+/// items, adding or removing one only moves the keys it gains or loses. Items with a non-positive
+/// weight get no points. No item gets more than 65536 points, so a huge weight can't exhaust
+/// memory, but it also means shares stop following weights past that many points (1638 units at
+/// the default 40 points per unit).
+///
+/// The hash is FNV-1a with a final mixing step rather than the standard library's hasher, and
+/// integers go into it in a fixed width and byte order, so the mapping is the same on every
+/// platform and Rust version for keys whose `Hash` impls are.
+#[derive(Clone, Debug)]
+pub struct ConsistentHashWeight<T> {
+    items: Vec<ConsistentHashItem<T>>,
+    ring: Vec<(u64, usize)>,
+    points_per_weight: usize,
+}
+
+impl<T> Default for ConsistentHashWeight<T> {
+    fn default() -> Self {
+        ConsistentHashWeight {
+            items: Vec::new(),
+            ring: Vec::new(),
+            points_per_weight: DEFAULT_POINTS_PER_WEIGHT,
+        }
+    }
+}
+
+impl<T: Hash> ConsistentHashWeight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// creates an empty ring that places `points` points per unit of weight, at least 1. More
+    /// points spread the keys more evenly at the cost of memory and slower updates.
+    pub fn with_points_per_weight(points: usize) -> Self {
+        ConsistentHashWeight {
+            points_per_weight: points.max(1),
+            ..Self::default()
+        }
+    }
+
+    /// adds an item owning a share of the keys proportional to `weight`.
+    pub fn add(&mut self, item: T, weight: isize) {
+        let index = self.items.len();
+        self.push_points(&item, weight, index);
+        self.items.push(ConsistentHashItem { item, weight });
+        self.ring.sort_unstable();
+    }
+
+    /// removes an item and returns it with its weight, or `None` if it isn't present. Only the
+    /// keys it owned move, each to the item owning the next point on the ring.
+    pub fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        self.ring.retain(|&(_, i)| i != index);
+        for point in &mut self.ring {
+            if point.1 > index {
+                point.1 -= 1;
+            }
+        }
+        Some((removed.item, removed.weight))
+    }
+
+    /// returns the item owning `key`, or `None` if no item has a positive weight.
+    pub fn get<K: Hash + ?Sized>(&self, key: &K) -> Option<&T> {
+        if self.ring.is_empty() {
+            return None;
+        }
+
+        let hash = hash_of(key);
+        let point = self.ring.partition_point(|&(h, _)| h < hash);
+        let (_, index) = self.ring[point % self.ring.len()];
+        Some(&self.items[index].item)
+    }
+
+    /// returns the number of items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// returns true if there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn push_points(&mut self, item: &T, weight: isize, index: usize) {
+        let points = (weight.max(0) as usize)
+            .saturating_mul(self.points_per_weight)
+            .min(MAX_POINTS_PER_ITEM);
+        self.ring.reserve(points);
+        for replica in 0..points {
+            self.ring.push((hash_of(&(item, replica)), index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_of, RingHasher, DEFAULT_POINTS_PER_WEIGHT, MAX_POINTS_PER_ITEM};
+    use crate::ConsistentHashWeight;
+    use core::hash::Hasher;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_consistent_hash_weight() {
+        let mut ch: ConsistentHashWeight<&str> = ConsistentHashWeight::new();
+        assert_eq!(ch.get("key"), None);
+        ch.add("server1", 3);
+        ch.add("server2", 1);
+        ch.add("server3", 0);
+
+        let owners: Vec<&str> = (0..10_000usize).map(|key| *ch.get(&key).unwrap()).collect();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for owner in &owners {
+            *counts.entry(owner).or_insert(0) += 1;
+        }
+        assert_eq!(counts.get("server3"), None);
+        assert!((6_500..8_500).contains(&counts["server1"]));
+
+        // the same key always maps to the same item
+        assert_eq!(ch.get(&42), ch.get(&42));
+
+        // adding an item only moves keys to it
+        ch.add("server4", 2);
+        let mut moved = 0;
+        for (key, owner) in owners.iter().enumerate() {
+            let now = *ch.get(&key).unwrap();
+            if now != *owner {
+                assert_eq!(now, "server4");
+                moved += 1;
+            }
+        }
+        assert!((2_500..4_200).contains(&moved));
+
+        // removing it moves exactly those keys back
+        assert_eq!(ch.remove(&"server4"), Some(("server4", 2)));
+        for (key, owner) in owners.iter().enumerate() {
+            assert_eq!(ch.get(&key), Some(owner));
+        }
+        assert_eq!(ch.len(), 3);
+    }
+
+    #[test]
+    fn test_portable_ring() {
+        // these don't depend on the target's word size or byte order
+        assert_eq!(hash_of(&1usize), hash_of(&1u64));
+        assert_eq!(hash_of(&-1isize), hash_of(&-1i64));
+        let mut bytes = RingHasher::new();
+        bytes.write(&[2, 1]);
+        assert_eq!(hash_of(&0x0102u16), bytes.finish());
+
+        let mut ch: ConsistentHashWeight<&str> = ConsistentHashWeight::new();
+        ch.add("server1", 1_000_000_000);
+        ch.add("server2", 1);
+        assert_eq!(
+            ch.ring.len(),
+            MAX_POINTS_PER_ITEM + DEFAULT_POINTS_PER_WEIGHT
+        );
+        assert!(ch.get(&7).is_some());
+    }
+}
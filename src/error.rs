@@ -0,0 +1,38 @@
+use core::fmt;
+
+/// Errors reported by the fallible balancer operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WeightError {
+    /// the weight is below zero.
+    NegativeWeight(isize),
+    /// the weight is zero, so the item could never be selected.
+    ZeroWeight,
+    /// there are no items to select from.
+    Empty,
+    /// there are items, but none has a positive weight.
+    ZeroTotalWeight,
+    /// the item isn't present.
+    ItemNotFound,
+    /// the balancer already holds as many items as its limit allows.
+    CapacityExceeded,
+    /// a rate limit refused the selection for now; there may be items to select later.
+    Throttled,
+}
+
+impl fmt::Display for WeightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeightError::NegativeWeight(weight) => write!(f, "negative weight {}", weight),
+            WeightError::ZeroWeight => write!(f, "zero weight"),
+            WeightError::Empty => write!(f, "no items to select from"),
+            WeightError::ZeroTotalWeight => write!(f, "no item has a positive weight"),
+            WeightError::ItemNotFound => write!(f, "item not found"),
+            WeightError::CapacityExceeded => write!(f, "item limit reached"),
+            WeightError::Throttled => write!(f, "selection throttled"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WeightError {}
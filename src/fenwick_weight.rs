@@ -0,0 +1,225 @@
+use super::Weight;
+use rand::prelude::{Rng, ThreadRng};
+
+#[derive(Clone, Debug)]
+struct FenwickWeightItem<T> {
+    item: T,
+    weight: isize,
+}
+
+/// FenwickWeight selects items at random in proportion to their weights, keeping the cumulative
+/// weights in a Fenwick (binary indexed) tree.
+///
+/// Selection draws a value in `[0, total)` and descends the tree, and `update_weight` adjusts it
+/// in place, both in O(log n). That makes it a good fit for large item sets whose weights change
+/// constantly, where rebuilding an alias table on every update would be too slow. Removal swaps
+/// the last item into the freed slot, so it also stays O(log n) once the item is found.
+///
+/// Items with a non-positive weight are never selected.
+#[derive(Debug, Default)]
+pub struct FenwickWeight<T, R = ThreadRng> {
+    items: Vec<FenwickWeightItem<T>>,
+    // 1-based: tree[i] holds the sum of the weights in (i - lowbit(i), i]
+    tree: Vec<i128>,
+    total: i128,
+    r: R,
+}
+
+impl<T: Clone> FenwickWeight<T> {
+    pub fn new() -> Self {
+        FenwickWeight::with_rng(rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> FenwickWeight<T, R> {
+    /// creates a balancer that draws from `rng`.
+    pub fn with_rng(rng: R) -> Self {
+        FenwickWeight {
+            items: Vec::new(),
+            tree: vec![0],
+            total: 0,
+            r: rng,
+        }
+    }
+
+    fn prefix(&self, mut i: usize) -> i128 {
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.tree[i];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    fn tree_add(&mut self, index: usize, delta: i128) {
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+        self.total += delta;
+    }
+
+    // returns the index of the first item whose cumulative weight exceeds `x`
+    fn search(&self, mut x: i128) -> usize {
+        let n = self.tree.len() - 1;
+        let mut pos = 0;
+        let mut step = if n == 0 {
+            0
+        } else {
+            1 << (usize::BITS - 1 - n.leading_zeros())
+        };
+        while step > 0 {
+            if pos + step <= n && self.tree[pos + step] <= x {
+                pos += step;
+                x -= self.tree[pos];
+            }
+            step >>= 1;
+        }
+        pos
+    }
+}
+
+impl<T: Clone, R: Rng> Weight for FenwickWeight<T, R> {
+    fn add(&mut self, item: T, weight: isize) {
+        let value = weight.max(0) as i128;
+        let n = self.tree.len();
+        let covered = self.prefix(n - 1) - self.prefix(n - (n & n.wrapping_neg()));
+        self.tree.push(value + covered);
+        self.total += value;
+        self.items.push(FenwickWeightItem { item, weight });
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let last = self.items.len() - 1;
+
+        self.tree_add(index, -(self.items[index].weight.max(0) as i128));
+        if index != last {
+            let moved = self.items[last].weight.max(0) as i128;
+            self.tree_add(last, -moved);
+            self.tree_add(index, moved);
+        }
+        // the last node only covers ranges ending at itself, so it can simply be dropped
+        self.tree.pop();
+
+        let removed = self.items.swap_remove(index);
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        let index = match self.items.iter().position(|w| w.item == *item) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let delta = weight.max(0) as i128 - self.items[index].weight.max(0) as i128;
+        self.items[index].weight = weight;
+        self.tree_add(index, delta);
+        true
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+        self.tree.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+        self.tree.truncate(1);
+        self.total = 0;
+    }
+
+    fn reset(&mut self) {}
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+        self.tree.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for FenwickWeight<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.total <= 0 {
+            return None;
+        }
+
+        let x = self.r.gen_range(0..self.total);
+        Some(self.items[self.search(x)].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FenwickWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_fenwick_weight() {
+        let mut fw: FenwickWeight<usize> = FenwickWeight::new();
+        for i in 0..10 {
+            fw.add(i, i as isize);
+        }
+
+        // the tree agrees with a plain prefix sum after adds, updates and removals
+        fw.update_weight(&3, 30);
+        fw.remove(&5);
+        fw.remove(&0);
+        fw.update_weight(&9, 0);
+        fw.add(10, 7);
+        let weights: Vec<isize> = fw.items.iter().map(|w| w.weight.max(0)).collect();
+        let mut sum = 0;
+        for (i, w) in weights.iter().enumerate() {
+            sum += *w as i128;
+            assert_eq!(fw.prefix(i + 1), sum);
+        }
+        assert_eq!(fw.total, sum);
+
+        let mut results: HashMap<usize, usize> = HashMap::new();
+        for _ in 0..sum as usize * 1000 {
+            *results.entry(fw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results.get(&0), None);
+        assert_eq!(results.get(&5), None);
+        assert_eq!(results.get(&9), None);
+        for (item, weight) in fw.all() {
+            if weight > 0 {
+                let expected = weight as f64 * 1000.0;
+                assert!((results[&item] as f64 - expected).abs() < expected * 0.1 + 100.0);
+            }
+        }
+
+        fw.remove_all();
+        assert_eq!(fw.next(), None);
+    }
+}
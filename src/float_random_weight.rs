@@ -0,0 +1,186 @@
+use rand::prelude::{Rng, ThreadRng};
+
+#[derive(Clone, Debug)]
+struct FloatRandWeightItem<T> {
+    item: T,
+    weight: f64,
+}
+
+/// FloatRandWeight is the random algorithm over `f64` weights, for weights that are naturally
+/// fractional such as probabilities or measured ratios.
+///
+/// The weights don't need to sum to 1.0: `next()` draws a uniform value in `[0, total)` and scans
+/// the cumulative weights. Negative, infinite and NaN weights count as 0 and are never selected.
+/// Finite weights whose sum overflows `f64` are drawn from relative to the largest weight.
+#[derive(Debug, Default)]
+pub struct FloatRandWeight<T, R = ThreadRng> {
+    items: Vec<FloatRandWeightItem<T>>,
+    total: f64,
+    r: R,
+}
+
+impl<T: Clone> FloatRandWeight<T> {
+    pub fn new() -> Self {
+        FloatRandWeight::with_rng(rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> FloatRandWeight<T, R> {
+    /// creates a balancer that draws from `rng`.
+    pub fn with_rng(rng: R) -> Self {
+        FloatRandWeight {
+            items: Vec::new(),
+            total: 0.0,
+            r: rng,
+        }
+    }
+
+    /// adds a weighted item for selection.
+    pub fn add(&mut self, item: T, weight: f64) {
+        let weight = if weight.is_finite() {
+            weight.max(0.0)
+        } else {
+            0.0
+        };
+        self.items.push(FloatRandWeightItem { item, weight });
+        self.total += weight;
+    }
+
+    /// returns all items.
+    pub fn all(&self) -> impl Iterator<Item = (T, f64)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    /// removes all weighted items.
+    pub fn remove_all(&mut self) {
+        self.items.clear();
+        self.total = 0.0;
+    }
+
+    /// returns the number of items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// returns true if there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for FloatRandWeight<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.total <= 0.0 {
+            return None;
+        }
+
+        // a sum past f64::MAX can't be drawn from, but the weights divided by the largest one
+        // can, as they add up to at most the number of items
+        let (scale, total) = if self.total.is_finite() {
+            (1.0, self.total)
+        } else {
+            let max = self
+                .items
+                .iter()
+                .map(|item| item.weight)
+                .fold(0.0, f64::max);
+            let total = self.items.iter().map(|item| item.weight / max).sum();
+            (1.0 / max, total)
+        };
+
+        let mut x = self.r.gen_range(0.0..total);
+        for item in &self.items {
+            let weight = item.weight * scale;
+            if x < weight {
+                return Some(item.item.clone());
+            }
+            x -= weight;
+        }
+
+        // rounding can leave a sliver past the last cumulative bound
+        self.items
+            .iter()
+            .rev()
+            .find(|item| item.weight > 0.0)
+            .map(|item| item.item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FloatRandWeight;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_float_rand_weight() {
+        let mut fw: FloatRandWeight<&str> = FloatRandWeight::new();
+        fw.add("server1", 2.5);
+        fw.add("server2", 1.5);
+        fw.add("server3", -1.0);
+        fw.add("server4", f64::NAN);
+        fw.add("server5", 1.0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..10000 {
+            *results.entry(fw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!(results["server1"] > 4500 && results["server1"] < 5500);
+        assert!(results["server2"] > 2500 && results["server2"] < 3500);
+        assert!(results["server5"] > 1500 && results["server5"] < 2500);
+        assert_eq!(results.get("server3"), None);
+        assert_eq!(results.get("server4"), None);
+
+        fw.remove_all();
+        assert_eq!(fw.next(), None);
+    }
+
+    #[test]
+    fn test_fractional_distribution() {
+        fn check_shares(mut fw: FloatRandWeight<&'static str, StdRng>) {
+            let mut results: HashMap<&str, usize> = HashMap::new();
+            for _ in 0..100_000 {
+                *results.entry(fw.next().unwrap()).or_insert(0) += 1;
+            }
+            for (item, share) in [("server1", 0.5), ("server2", 0.3), ("server3", 0.2)].iter() {
+                let observed = results[item] as f64 / 100_000.0;
+                assert!((observed - share).abs() < 0.01, "{} got {}", item, observed);
+            }
+        }
+
+        let mut fw = FloatRandWeight::with_rng(StdRng::seed_from_u64(1));
+        fw.add("server1", 0.5);
+        fw.add("server2", 0.3);
+        fw.add("server3", 0.2);
+        check_shares(fw);
+
+        // the weights don't need to sum to 1.0: scaling them all keeps the distribution
+        let mut scaled = FloatRandWeight::with_rng(StdRng::seed_from_u64(2));
+        scaled.add("server1", 5.0);
+        scaled.add("server2", 3.0);
+        scaled.add("server3", 2.0);
+        check_shares(scaled);
+    }
+
+    #[test]
+    fn test_total_overflow() {
+        let mut fw = FloatRandWeight::with_rng(StdRng::seed_from_u64(1));
+        fw.add("server1", 1e308);
+        fw.add("server2", 1e308);
+        fw.add("server3", 1e307);
+        fw.add("server4", 0.0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..21_000 {
+            *results.entry(fw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!((9_500..10_500).contains(&results["server1"]));
+        assert!((9_500..10_500).contains(&results["server2"]));
+        assert!((700..1_300).contains(&results["server3"]));
+        assert_eq!(results.get("server4"), None);
+    }
+}
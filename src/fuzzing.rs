@@ -0,0 +1,123 @@
+//! Operation replay support for fuzzing the balancers.
+//!
+//! A fuzzer produces an arbitrary sequence of [`Op`]s and feeds them one by one to
+//! [`Replay::apply`], which performs the operation and panics as soon as an invariant is broken.
+
+use super::Weight;
+
+/// A single mutation or selection performed against a balancer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op<T> {
+    Add(T, isize),
+    Remove(T),
+    Update(T, isize),
+    Next,
+    Reset,
+}
+
+/// Applies [`Op`]s to a balancer and checks its invariants after each one.
+pub trait Replay: Weight {
+    /// performs `op`, panicking if the balancer ends up in an inconsistent state.
+    fn apply(&mut self, op: Op<Self::Item>);
+}
+
+impl<W> Replay for W
+where
+    W: Weight,
+    W::Item: PartialEq + Clone,
+{
+    fn apply(&mut self, op: Op<W::Item>) {
+        match op {
+            Op::Add(item, weight) => {
+                let len = self.len();
+                self.add(item.clone(), weight);
+                assert_eq!(self.len(), len + 1);
+                assert!(self.contains_with_weight(&item, weight));
+            }
+            Op::Remove(item) => {
+                let before = Weight::all(self).filter(|(i, _)| *i == item).count();
+                let removed = self.remove(&item);
+                let after = Weight::all(self).filter(|(i, _)| *i == item).count();
+                assert_eq!(removed.is_some(), before > 0);
+                assert_eq!(after, before.saturating_sub(1));
+            }
+            Op::Update(item, weight) => {
+                let present = Weight::all(self).any(|(i, _)| i == item);
+                assert_eq!(self.update_weight(&item, weight), present);
+                if present {
+                    assert!(self.contains_with_weight(&item, weight));
+                }
+            }
+            Op::Next => {
+                // only items with a positive weight may be selected
+                if let Some(picked) = self.next() {
+                    assert!(Weight::all(self).any(|(i, w)| i == picked && w > 0));
+                }
+            }
+            Op::Reset => self.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "random")]
+    use crate::RandWeight;
+    use crate::{
+        fuzzing::{Op, Replay},
+        RoundrobinWeight, SmoothWeight,
+    };
+
+    fn ops() -> Vec<Op<u8>> {
+        vec![
+            Op::Add(1, 5),
+            Op::Add(2, 2),
+            Op::Next,
+            Op::Add(3, 3),
+            Op::Add(4, 0),
+            Op::Add(5, -3),
+            Op::Next,
+            Op::Update(2, 4),
+            Op::Next,
+            Op::Remove(1),
+            Op::Next,
+            Op::Reset,
+            Op::Next,
+            Op::Remove(2),
+            Op::Remove(3),
+            Op::Next,
+            Op::Remove(4),
+            Op::Next,
+        ]
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut sw: SmoothWeight<u8> = SmoothWeight::new();
+        let mut rrw: RoundrobinWeight<u8> = RoundrobinWeight::new();
+        for op in ops() {
+            sw.apply(op.clone());
+            rrw.apply(op);
+        }
+        assert_eq!(sw.next(), None);
+        assert_eq!(rrw.next(), None);
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw: RandWeight<u8> = RandWeight::new();
+            for op in ops() {
+                rw.apply(op);
+            }
+            assert_eq!(rw.next(), None);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            let mut isw: crate::IndexedSmoothWeight<u8> = crate::IndexedSmoothWeight::new();
+            for op in ops() {
+                isw.apply(op);
+            }
+            assert_eq!(isw.next(), None);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use super::Weight;
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug)]
+struct HandleWeightItem<T> {
+    item: T,
+    weight: isize,
+    current_weight: i128,
+    effective_weight: isize,
+}
+
+#[derive(Clone, Debug)]
+struct Slot<T> {
+    generation: u32,
+    // where the slot sits in `occupied` while it holds an item
+    position: usize,
+    entry: Option<HandleWeightItem<T>>,
+}
+
+/// ItemHandle refers to an item inserted into a `HandleSmoothWeight`.
+///
+/// It is the item's slot together with the slot's generation. Removing the item bumps the
+/// generation, so the handle stops matching once the slot is freed or reused by another item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ItemHandle {
+    index: usize,
+    generation: u32,
+}
+
+/// HandleSmoothWeight is the smooth weighted round-robin of `SmoothWeight` over a slab of slots.
+/// `insert` returns an `ItemHandle`, so an item's weight can later be updated or the item removed
+/// in O(1) without comparing items, and `T` needs neither `PartialEq` nor `Hash` for it.
+///
+/// Items never move between slots. A removed item's slot is reused by the next insertion, and the
+/// removed item's current weight is set aside and handed to the first selectable item on the next
+/// selection, so the current weights keep summing to zero without removal scanning the items.
+/// The occupied slots are also kept in a dense list, so `get(index)` is O(1); an index counts
+/// items in that list, where removal moves the last item into the removed one's place.
+///
+/// Slots are never released, not even by `remove_all`, so that handles from before it stay
+/// detectably stale: `remove_all` drops the items and releases the rest of the storage, but keeps
+/// the emptied slots for reuse.
+#[derive(Debug)]
+pub struct HandleSmoothWeight<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    occupied: Vec<usize>,
+    // current weight of removed items, not yet handed to a remaining one
+    pending: i128,
+}
+
+impl<T> Default for HandleSmoothWeight<T> {
+    fn default() -> Self {
+        HandleSmoothWeight {
+            slots: Vec::new(),
+            free: Vec::new(),
+            occupied: Vec::new(),
+            pending: 0,
+        }
+    }
+}
+
+impl<T: Clone> HandleSmoothWeight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds a weighted item for selection and returns a handle to it.
+    pub fn insert(&mut self, item: T, weight: isize) -> ItemHandle {
+        let entry = HandleWeightItem {
+            item,
+            weight,
+            current_weight: 0,
+            effective_weight: weight,
+        };
+
+        let position = self.occupied.len();
+        let index = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.position = position;
+                slot.entry = Some(entry);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    position,
+                    entry: Some(entry),
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        self.occupied.push(index);
+        ItemHandle {
+            index,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    /// returns the item behind `handle` with its weight, or `None` if the handle is stale.
+    pub fn get_by_handle(&self, handle: ItemHandle) -> Option<(&T, isize)> {
+        self.slots
+            .get(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.entry.as_ref())
+            .map(|w| (&w.item, w.weight))
+    }
+
+    /// updates the weight of the item behind `handle`. Returns false if the handle is stale.
+    pub fn update_weight_by_handle(&mut self, handle: ItemHandle, weight: isize) -> bool {
+        match self.entry_mut(handle) {
+            Some(w) => {
+                w.weight = weight;
+                w.effective_weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// removes the item behind `handle` and returns it with its weight, or `None` if the handle
+    /// is stale.
+    pub fn remove_by_handle(&mut self, handle: ItemHandle) -> Option<(T, isize)> {
+        self.entry_mut(handle)?;
+        Some(self.vacate(handle.index))
+    }
+
+    fn entry_mut(&mut self, handle: ItemHandle) -> Option<&mut HandleWeightItem<T>> {
+        self.slots
+            .get_mut(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.entry.as_mut())
+    }
+
+    fn vacate(&mut self, index: usize) -> (T, isize) {
+        let slot = &mut self.slots[index];
+        let removed = slot.entry.take().unwrap();
+        slot.generation = slot.generation.wrapping_add(1);
+        let position = slot.position;
+        self.free.push(index);
+        self.occupied.swap_remove(position);
+        if let Some(&moved) = self.occupied.get(position) {
+            self.slots[moved].position = position;
+        }
+
+        self.pending += removed.current_weight;
+        (removed.item, removed.weight)
+    }
+
+    fn entry(&self, index: usize) -> &HandleWeightItem<T> {
+        self.slots[index].entry.as_ref().unwrap()
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &HandleWeightItem<T>> {
+        self.occupied.iter().map(move |&index| self.entry(index))
+    }
+
+    //https://github.com/phusion/nginx/commit/27e94984486058d73157038f7950a0a36ecc6e35
+    fn next_smooth_weighted(&mut self) -> Option<usize> {
+        let mut total = 0;
+        let mut best: Option<usize> = None;
+
+        for &i in &self.occupied {
+            let w = match self.slots[i].entry.as_mut() {
+                // zero-weight items are registered but never selected
+                Some(w) if w.weight > 0 => w,
+                _ => continue,
+            };
+
+            // the first selectable item takes over what removed items left behind
+            w.current_weight += core::mem::take(&mut self.pending);
+            w.current_weight += w.effective_weight as i128;
+            total += w.effective_weight as i128;
+            if w.effective_weight < w.weight {
+                w.effective_weight += 1;
+            }
+
+            let current_weight = w.current_weight;
+            let is_best = best.is_none_or(|b| {
+                current_weight > self.slots[b].entry.as_ref().unwrap().current_weight
+            });
+            if is_best {
+                best = Some(i);
+            }
+        }
+
+        let best = match best {
+            Some(best) => best,
+            None => {
+                // nothing can take the left-over current weight, so start over once something can
+                self.reset();
+                return None;
+            }
+        };
+        self.slots[best].entry.as_mut().unwrap().current_weight -= total;
+        Some(best)
+    }
+}
+
+impl<T: Clone> Weight for HandleSmoothWeight<T> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.insert(item, weight);
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = *self
+            .occupied
+            .iter()
+            .find(|&&index| self.entry(index).item == *item)?;
+        Some(self.vacate(index))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        let slots = &mut self.slots;
+        let w = self
+            .occupied
+            .iter()
+            .find(|&&index| slots[index].entry.as_ref().unwrap().item == *item)
+            .map(|&index| slots[index].entry.as_mut().unwrap());
+        match w {
+            Some(w) => {
+                w.weight = weight;
+                w.effective_weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.entries().map(|w| (w.item.clone(), w.weight))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&T, isize)> + '_ {
+        self.entries().map(|w| (&w.item, w.weight))
+    }
+
+    fn remove_all(&mut self) {
+        for index in self.occupied.drain(..) {
+            let slot = &mut self.slots[index];
+            slot.entry = None;
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(index);
+        }
+        self.occupied.shrink_to_fit();
+        self.pending = 0;
+    }
+
+    fn reset(&mut self) {
+        for &index in &self.occupied {
+            let w = self.slots[index].entry.as_mut().unwrap();
+            w.current_weight = 0;
+            w.effective_weight = w.weight;
+        }
+        self.pending = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.occupied.len()
+    }
+
+    fn total_weight(&self) -> isize {
+        let total: i128 = self.entries().map(|w| w.weight.max(0) as i128).sum();
+        total.min(isize::MAX as i128) as isize
+    }
+
+    fn min_weight(&self) -> Option<isize> {
+        self.entries().map(|w| w.weight).min()
+    }
+
+    fn max_weight(&self) -> Option<isize> {
+        self.entries().map(|w| w.weight).max()
+    }
+
+    /// Indexes count items in the order of the dense list of occupied slots, which removal
+    /// reorders.
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        let w = self.entry(*self.occupied.get(index)?);
+        Some((&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.slots
+            .reserve(additional.saturating_sub(self.free.len()));
+        self.occupied.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.entries().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> Iterator for HandleSmoothWeight<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.next_smooth_weighted()?;
+        self.slots[index].entry.as_ref().map(|w| w.item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{HandleSmoothWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_handles() {
+        let mut sw: HandleSmoothWeight<&str> = HandleSmoothWeight::new();
+        let server1 = sw.insert("server1", 5);
+        let server2 = sw.insert("server2", 2);
+        let server3 = sw.insert("server3", 3);
+
+        assert_eq!(sw.remove_by_handle(server2), Some(("server2", 2)));
+        assert_eq!(sw.remove_by_handle(server2), None);
+        assert_eq!(sw.len(), 2);
+
+        // the freed slot is reused, but the old handle doesn't reach the new item
+        let server4 = sw.insert("server4", 1);
+        assert_ne!(server2, server4);
+        assert_eq!(sw.get_by_handle(server2), None);
+        assert!(!sw.update_weight_by_handle(server2, 7));
+        assert_eq!(sw.get_by_handle(server4), Some((&"server4", 1)));
+
+        assert!(sw.update_weight_by_handle(server4, 0));
+        assert!(sw.update_weight_by_handle(server3, 5));
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server3"], 50);
+        assert_eq!(results.get("server4"), None);
+
+        assert_eq!(sw.total_weight(), 10);
+        assert_eq!(sw.min_weight(), Some(0));
+        assert_eq!(sw.max_weight(), Some(5));
+
+        sw.remove_all();
+        assert_eq!(sw.get_by_handle(server1), None);
+        assert_eq!(sw.max_weight(), None);
+        assert_eq!(sw.next(), None);
+        let server5 = sw.insert("server5", 1);
+        assert_eq!(sw.get_by_handle(server5), Some((&"server5", 1)));
+        assert_eq!(sw.get_by_handle(server3), None);
+    }
+
+    #[test]
+    fn test_remove_hand_off() {
+        let mut sw: HandleSmoothWeight<&str> = HandleSmoothWeight::new();
+        let server1 = sw.insert("server1", 3);
+        let server2 = sw.insert("server2", 1);
+        let server3 = sw.insert("server3", 2);
+        sw.next_n(3);
+
+        // server3 moves into server1's place, and the next selection settles the current weights
+        sw.remove_by_handle(server1);
+        assert_eq!(sw.get(0), Some((&"server3", 2)));
+        assert_eq!(sw.get(1), Some((&"server2", 1)));
+        sw.next();
+        assert_eq!(sw.pending, 0);
+        assert_eq!(sw.entries().map(|w| w.current_weight).sum::<i128>(), 0);
+
+        // with nothing selectable left, the rotation starts over
+        sw.update_weight_by_handle(server3, 0);
+        sw.next();
+        sw.remove_by_handle(server2);
+        assert_eq!(sw.next(), None);
+        assert!(sw.entries().all(|w| w.current_weight == 0));
+
+        sw.update_weight_by_handle(server3, 2);
+        sw.insert("server1", 3);
+        let mut fresh: HandleSmoothWeight<&str> = HandleSmoothWeight::new();
+        fresh.insert("server3", 2);
+        fresh.insert("server1", 3);
+        assert_eq!(sw.next_n(10), fresh.next_n(10));
+    }
+}
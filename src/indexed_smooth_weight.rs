@@ -0,0 +1,268 @@
+use super::{rotation::hand_off, Weight};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Clone, Debug)]
+struct IndexedWeightItem<T> {
+    item: T,
+    weight: isize,
+    current_weight: i128,
+    effective_weight: isize,
+}
+
+/// IndexedSmoothWeight is the smooth weighted round-robin of `SmoothWeight` with a `HashMap` from
+/// each item to its slot, so `contains`, `update_weight` and `remove` are O(1) instead of a scan.
+///
+/// Items are unique: adding an item that is already present just changes its weight. Removal
+/// swaps the last item into the freed slot and hands it the removed item's current weight, which
+/// keeps the current weights summing to zero without touching every other item. If the moved item
+/// can't be selected, another one takes the current weight, and once none can, all current weights
+/// start over from 0. Items with a non-positive weight are never selected.
+#[derive(Debug)]
+pub struct IndexedSmoothWeight<T> {
+    items: Vec<IndexedWeightItem<T>>,
+    index: HashMap<T, usize>,
+}
+
+impl<T> Default for IndexedSmoothWeight<T> {
+    fn default() -> Self {
+        IndexedSmoothWeight {
+            items: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash> IndexedSmoothWeight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //https://github.com/phusion/nginx/commit/27e94984486058d73157038f7950a0a36ecc6e35
+    fn next_smooth_weighted(&mut self) -> Option<usize> {
+        let mut total = 0;
+        let mut best: Option<(usize, i128)> = None;
+
+        for (i, w) in self.items.iter_mut().enumerate() {
+            // zero-weight items are registered but never selected
+            if w.weight <= 0 {
+                continue;
+            }
+
+            w.current_weight += w.effective_weight as i128;
+            total += w.effective_weight as i128;
+            if w.effective_weight < w.weight {
+                w.effective_weight += 1;
+            }
+
+            if best.is_none_or(|(_, current)| w.current_weight > current) {
+                best = Some((i, w.current_weight));
+            }
+        }
+
+        let (index, _) = best?;
+        self.items[index].current_weight -= total;
+        Some(index)
+    }
+}
+
+impl<T: Clone + Eq + Hash> Weight for IndexedSmoothWeight<T> {
+    fn add(&mut self, item: T, weight: isize) {
+        if self.update_weight(&item, weight) {
+            return;
+        }
+
+        self.index.insert(item.clone(), self.items.len());
+        self.items.push(IndexedWeightItem {
+            item,
+            weight,
+            current_weight: 0,
+            effective_weight: weight,
+        });
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)> {
+        let index = self.index.remove(item)?;
+        let removed = self.items.swap_remove(index);
+        if let Some(moved) = self.items.get(index) {
+            *self.index.get_mut(&moved.item).unwrap() = index;
+        }
+        hand_off(&mut self.items, index, removed.current_weight, |w| {
+            Some((w.weight, &mut w.current_weight))
+        });
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool {
+        match self.index.get(item) {
+            Some(&index) => {
+                let w = &mut self.items[index];
+                w.weight = weight;
+                w.effective_weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    // items are unique, so `new` can't take the place of `old` if it's already present elsewhere
+    fn replace(&mut self, old: &T, new: T) -> bool {
+        if new != *old && self.index.contains_key(&new) {
+            return false;
+        }
+
+        match self.index.remove(old) {
+            Some(index) => {
+                self.items[index].item = new.clone();
+                self.index.insert(new, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+        self.index.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+        self.index.clear();
+    }
+
+    fn reset(&mut self) {
+        for w in &mut self.items {
+            w.current_weight = 0;
+            w.effective_weight = w.weight;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+        self.index.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool {
+        self.index.contains_key(item)
+    }
+}
+
+impl<T: Clone + Eq + Hash> Iterator for IndexedSmoothWeight<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.next_smooth_weighted()?;
+        Some(self.items[index].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{IndexedSmoothWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_indexed_smooth_weight() {
+        let mut sw: IndexedSmoothWeight<&str> = IndexedSmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+        sw.add("server4", 1);
+
+        // server4 is swapped into server2's slot and must still be found there
+        assert_eq!(sw.remove(&"server2"), Some(("server2", 2)));
+        assert!(!sw.contains(&"server2"));
+        assert!(sw.contains(&"server4"));
+        assert!(sw.update_weight(&"server4", 2));
+        assert!(sw.contains_with_weight(&"server4", 2));
+        assert_eq!(sw.remove(&"server4"), Some(("server4", 2)));
+        assert_eq!(sw.len(), 2);
+
+        sw.add("server3", 5);
+        assert_eq!(sw.len(), 2);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server3"], 50);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut sw: IndexedSmoothWeight<&str> = IndexedSmoothWeight::new();
+        sw.add("server1", 2);
+        sw.add("server2", 1);
+        assert_eq!(sw.next(), Some("server1"));
+
+        // the new value takes over server1's slot and its place in the rotation
+        assert!(sw.replace(&"server1", "server3"));
+        assert!(!sw.replace(&"server2", "server3"));
+        assert_eq!(sw.next(), Some("server2"));
+        assert_eq!(sw.next(), Some("server3"));
+        assert!(sw.update_weight(&"server3", 5));
+        assert!(!sw.contains(&"server1"));
+    }
+
+    #[test]
+    fn test_non_positive_weights() {
+        let mut sw: IndexedSmoothWeight<&str> = IndexedSmoothWeight::new();
+        sw.add("zero", 0);
+        assert_eq!(sw.next(), None);
+        sw.add("neg", -3);
+        assert_eq!(sw.next(), None);
+
+        sw.add("server1", 1);
+        assert_eq!(sw.next_n(3), vec!["server1"; 3]);
+    }
+
+    #[test]
+    fn test_remove_hand_off() {
+        let mut sw: IndexedSmoothWeight<&str> = IndexedSmoothWeight::new();
+        sw.add("server1", 3);
+        sw.add("server2", 1);
+        sw.add("server3", 0);
+        sw.next();
+
+        // server3 moves into server1's slot but can't be selected, so server2 takes the weight
+        assert_eq!(sw.remove(&"server1"), Some(("server1", 3)));
+        let sum: i128 = sw
+            .items
+            .iter()
+            .filter(|w| w.weight > 0)
+            .map(|w| w.current_weight)
+            .sum();
+        assert_eq!(sum, 0);
+
+        // removing the last selectable item starts everything over
+        sw.next();
+        sw.remove(&"server2");
+        assert!(sw.items.iter().all(|w| w.current_weight == 0));
+
+        // and refilling runs in phase again
+        sw.add("server1", 3);
+        sw.add("server2", 1);
+        let mut fresh: IndexedSmoothWeight<&str> = IndexedSmoothWeight::new();
+        fresh.add("server3", 0);
+        fresh.add("server1", 3);
+        fresh.add("server2", 1);
+        assert_eq!(sw.next_n(8), fresh.next_n(8));
+    }
+}
@@ -0,0 +1,71 @@
+use super::{SmoothWeight, Weight};
+use alloc::vec::Vec;
+
+/// An iterator that merges several sources, pulling from each in proportion to its weight.
+///
+/// Created by [`interleave`].
+pub struct Interleave<I> {
+    sources: Vec<Option<I>>,
+    schedule: SmoothWeight<usize>,
+}
+
+/// merges `sources` into one iterator that pulls from each source in proportion to its weight,
+/// spread out with the smooth weighted round-robin algorithm.
+///
+/// A source that runs dry is dropped and the remaining ones share its portion according to their
+/// weights. Sources with a non-positive weight are never pulled from.
+pub fn interleave<I: Iterator>(sources: Vec<(I, isize)>) -> Interleave<I> {
+    let mut schedule = SmoothWeight::new();
+    let sources = sources
+        .into_iter()
+        .enumerate()
+        .map(|(i, (source, weight))| {
+            if weight > 0 {
+                schedule.add(i, weight);
+                Some(source)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    Interleave { sources, schedule }
+}
+
+impl<I: Iterator> Iterator for Interleave<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            let i = self.schedule.next()?;
+            match self.sources[i].as_mut().and_then(Iterator::next) {
+                Some(item) => return Some(item),
+                None => {
+                    self.sources[i] = None;
+                    self.schedule.remove(&i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interleave;
+    use std::iter;
+
+    #[test]
+    fn test_interleave() {
+        let merged: String = interleave(vec![
+            (
+                Box::new(iter::repeat_n('a', 3)) as Box<dyn Iterator<Item = char>>,
+                2,
+            ),
+            (Box::new(iter::repeat_n('b', 5)), 1),
+            (Box::new(iter::repeat('c')), 0),
+        ])
+        .collect();
+
+        assert_eq!(merged, "abaabbbb");
+    }
+}
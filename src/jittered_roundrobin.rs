@@ -0,0 +1,204 @@
+use super::Weight;
+use rand::prelude::{Rng, ThreadRng};
+
+#[derive(Clone, Debug)]
+struct JitteredItem<T> {
+    item: T,
+    weight: isize,
+    current_weight: i128,
+}
+
+/// JitteredRoundrobin is the smooth weighted round-robin of `SmoothWeight` with a random
+/// tie-break, so the exact sequence can't be predicted while the long-run proportions stay those
+/// of the weights.
+///
+/// Every round each item's current weight grows by its weight as usual, but the item to select is
+/// the one with the highest current weight plus a random offset drawn from `[0, jitter]`. The
+/// selected item still gives back the total weight, and an item whose current weight is more
+/// than `jitter` ahead of every other one always wins, since no offset can make up the gap. So no
+/// item can run far ahead without being selected: current weights stay bounded and each item's
+/// share of `n` selections is off by a bounded amount, not one growing with `n`. A larger `jitter`
+/// shuffles the sequence more, over longer stretches; a jitter of 0 gives the plain smooth
+/// sequence. Items with a non-positive weight are never selected.
+#[derive(Debug, Default)]
+pub struct JitteredRoundrobin<T, R = ThreadRng> {
+    items: Vec<JitteredItem<T>>,
+    jitter: isize,
+    r: R,
+}
+
+impl<T: Clone> JitteredRoundrobin<T> {
+    /// creates a balancer whose tie-break offsets go up to `jitter`, in weight units. Negative
+    /// values count as 0.
+    pub fn new(jitter: isize) -> Self {
+        JitteredRoundrobin::with_rng(jitter, rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> JitteredRoundrobin<T, R> {
+    /// creates a balancer with offsets up to `jitter` that draws them from `rng`.
+    pub fn with_rng(jitter: isize, rng: R) -> Self {
+        JitteredRoundrobin {
+            items: Vec::new(),
+            jitter: jitter.max(0),
+            r: rng,
+        }
+    }
+
+    /// changes the largest tie-break offset. Negative values count as 0.
+    pub fn set_jitter(&mut self, jitter: isize) {
+        self.jitter = jitter.max(0);
+    }
+
+    /// returns the largest tie-break offset.
+    pub fn jitter(&self) -> isize {
+        self.jitter
+    }
+
+    fn next_jittered(&mut self) -> Option<usize> {
+        let mut total = 0;
+        let mut best: Option<(usize, i128)> = None;
+
+        for i in 0..self.items.len() {
+            let w = &mut self.items[i];
+            if w.weight <= 0 {
+                continue;
+            }
+
+            w.current_weight += w.weight as i128;
+            total += w.weight as i128;
+
+            let offset = if self.jitter > 0 {
+                self.r.gen_range(0..=self.jitter) as i128
+            } else {
+                0
+            };
+            let score = w.current_weight + offset;
+            if best.is_none_or(|(_, s)| score > s) {
+                best = Some((i, score));
+            }
+        }
+
+        let (index, _) = best?;
+        self.items[index].current_weight -= total;
+        Some(index)
+    }
+}
+
+impl<T: Clone, R: Rng> Weight for JitteredRoundrobin<T, R> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(JitteredItem {
+            item,
+            weight,
+            current_weight: 0,
+        });
+    }
+
+    /// The removed item's current weight is handed to the heaviest remaining item, so the current
+    /// weights keep summing to zero.
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        if let Some(w) = self.items.iter_mut().max_by_key(|w| w.weight) {
+            w.current_weight += removed.current_weight;
+        }
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.items.clear();
+        self.items.shrink_to_fit();
+    }
+
+    fn reset(&mut self) {
+        for w in &mut self.items {
+            w.current_weight = 0;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for JitteredRoundrobin<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.next_jittered()?;
+        Some(self.items[index].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{JitteredRoundrobin, SmoothWeight, Weight};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_jittered_roundrobin() {
+        let mut a = JitteredRoundrobin::with_rng(4, StdRng::seed_from_u64(1));
+        let mut b = JitteredRoundrobin::with_rng(4, StdRng::seed_from_u64(2));
+        for w in [&mut a, &mut b].iter_mut() {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+            w.add("server4", 0);
+        }
+        assert_ne!(a.next_n(50), b.next_n(50));
+
+        // the shares stay within a small bound of the weights however long it runs
+        a.reset();
+        let counts = a.sample(10_000);
+        assert_eq!(counts.get("server4"), None);
+        assert!((4_990..=5_010).contains(&counts["server1"]));
+        assert!((1_990..=2_010).contains(&counts["server2"]));
+        assert!((2_990..=3_010).contains(&counts["server3"]));
+
+        // without jitter this is the plain smooth sequence
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.extend(vec![("server1", 5), ("server2", 2), ("server3", 3)]);
+        a.remove(&"server4");
+        a.reset();
+        a.set_jitter(-1);
+        assert_eq!(a.jitter(), 0);
+        assert_eq!(a.next_n(20), sw.next_n(20));
+    }
+}
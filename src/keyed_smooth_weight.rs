@@ -0,0 +1,255 @@
+use super::rotation::hand_off;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Clone, Debug)]
+struct KeyedWeightItem<K, V> {
+    key: K,
+    value: V,
+    weight: isize,
+    current_weight: i128,
+    effective_weight: isize,
+}
+
+/// KeyedSmoothWeight is the smooth weighted round-robin of `SmoothWeight` over values that are
+/// identified by a separate key, e.g. backend structs looked up by their id. `update_weight`,
+/// `remove` and lookups go through a `HashMap` from key to slot, so they are O(1) and the values
+/// need neither `PartialEq` nor `Clone`.
+///
+/// `next()` lends out the selected value instead of cloning it, so this can't be an `Iterator`
+/// and doesn't implement `Weight`. Keys are unique: adding a key that is already present replaces
+/// its value and weight. Removal swaps the last entry into the freed slot and hands it the removed
+/// entry's current weight, or to another selectable entry if that one isn't, like
+/// `IndexedSmoothWeight`. Entries with a non-positive weight are never selected.
+#[derive(Debug)]
+pub struct KeyedSmoothWeight<K, V> {
+    items: Vec<KeyedWeightItem<K, V>>,
+    index: HashMap<K, usize>,
+}
+
+impl<K, V> Default for KeyedSmoothWeight<K, V> {
+    fn default() -> Self {
+        KeyedSmoothWeight {
+            items: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> KeyedSmoothWeight<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds `value` under `key` for selection. If `key` is already present, its value and weight
+    /// are replaced, keeping its place in the rotation, and the old value is returned.
+    pub fn add(&mut self, key: K, value: V, weight: isize) -> Option<V> {
+        if let Some(&index) = self.index.get(&key) {
+            let w = &mut self.items[index];
+            w.weight = weight;
+            w.effective_weight = weight;
+            return Some(std::mem::replace(&mut w.value, value));
+        }
+
+        self.index.insert(key.clone(), self.items.len());
+        self.items.push(KeyedWeightItem {
+            key,
+            value,
+            weight,
+            current_weight: 0,
+            effective_weight: weight,
+        });
+        None
+    }
+
+    /// removes the entry under `key` and returns its value with its weight, or `None` if the key
+    /// isn't present.
+    pub fn remove(&mut self, key: &K) -> Option<(V, isize)> {
+        let index = self.index.remove(key)?;
+        let removed = self.items.swap_remove(index);
+        if let Some(moved) = self.items.get(index) {
+            *self.index.get_mut(&moved.key).unwrap() = index;
+        }
+        hand_off(&mut self.items, index, removed.current_weight, |w| {
+            Some((w.weight, &mut w.current_weight))
+        });
+        Some((removed.value, removed.weight))
+    }
+
+    /// updates the weight of the entry under `key`. Returns false if the key isn't present.
+    pub fn update_weight(&mut self, key: &K, weight: isize) -> bool {
+        match self.index.get(key) {
+            Some(&index) => {
+                let w = &mut self.items[index];
+                w.weight = weight;
+                w.effective_weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// returns the value under `key` with its weight.
+    pub fn get(&self, key: &K) -> Option<(&V, isize)> {
+        self.index.get(key).map(|&index| {
+            let w = &self.items[index];
+            (&w.value, w.weight)
+        })
+    }
+
+    /// returns the value under `key` for updating it in place. The weight is left alone.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = *self.index.get(key)?;
+        Some(&mut self.items[index].value)
+    }
+
+    /// returns true if an entry is stored under `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// selects the next value, or returns `None` if no entry has a positive weight.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&V> {
+        let index = self.next_smooth_weighted()?;
+        Some(&self.items[index].value)
+    }
+
+    /// selects the next entry like `next()`, returning its key as well.
+    pub fn next_entry(&mut self) -> Option<(&K, &V)> {
+        let index = self.next_smooth_weighted()?;
+        let w = &self.items[index];
+        Some((&w.key, &w.value))
+    }
+
+    /// removes all entries.
+    pub fn remove_all(&mut self) {
+        self.items.clear();
+        self.index.clear();
+    }
+
+    /// restarts the rotation.
+    pub fn reset(&mut self) {
+        for w in &mut self.items {
+            w.current_weight = 0;
+            w.effective_weight = w.weight;
+        }
+    }
+
+    /// returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// returns true if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    //https://github.com/phusion/nginx/commit/27e94984486058d73157038f7950a0a36ecc6e35
+    fn next_smooth_weighted(&mut self) -> Option<usize> {
+        let mut total = 0;
+        let mut best: Option<(usize, i128)> = None;
+
+        for (i, w) in self.items.iter_mut().enumerate() {
+            if w.weight <= 0 {
+                continue;
+            }
+
+            w.current_weight += w.effective_weight as i128;
+            total += w.effective_weight as i128;
+            if w.effective_weight < w.weight {
+                w.effective_weight += 1;
+            }
+
+            if best.is_none_or(|(_, current)| w.current_weight > current) {
+                best = Some((i, w.current_weight));
+            }
+        }
+
+        let (index, _) = best?;
+        self.items[index].current_weight -= total;
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KeyedSmoothWeight;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq)]
+    struct Backend {
+        addr: &'static str,
+        healthy: bool,
+    }
+
+    #[test]
+    fn test_keyed_smooth_weight() {
+        let mut kw: KeyedSmoothWeight<String, Backend> = KeyedSmoothWeight::new();
+        let backend = |addr| Backend {
+            addr,
+            healthy: true,
+        };
+        assert_eq!(kw.add("a".to_string(), backend("10.0.0.1"), 5), None);
+        kw.add("b".to_string(), backend("10.0.0.2"), 2);
+        kw.add("c".to_string(), backend("10.0.0.3"), 3);
+        kw.add("d".to_string(), backend("10.0.0.4"), 0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(kw.next().unwrap().addr).or_insert(0) += 1;
+        }
+        assert_eq!(results["10.0.0.1"], 50);
+        assert_eq!(results["10.0.0.2"], 20);
+        assert_eq!(results["10.0.0.3"], 30);
+        assert_eq!(results.get("10.0.0.4"), None);
+
+        let key = "a".to_string();
+        kw.get_mut(&key).unwrap().healthy = false;
+        assert_eq!(kw.get(&key).map(|(v, w)| (v.healthy, w)), Some((false, 5)));
+
+        let old = kw.add(key.clone(), backend("10.0.0.5"), 5);
+        assert_eq!(old.map(|b| b.addr), Some("10.0.0.1"));
+        assert!(kw.update_weight(&"b".to_string(), 0));
+        assert!(!kw.update_weight(&"e".to_string(), 1));
+        assert_eq!(
+            kw.remove(&"c".to_string()).map(|(b, w)| (b.addr, w)),
+            Some(("10.0.0.3", 3))
+        );
+        assert!(!kw.contains_key(&"c".to_string()));
+        assert_eq!(kw.len(), 3);
+
+        for _ in 0..10 {
+            assert_eq!(
+                kw.next_entry().map(|(k, b)| (k.as_str(), b.addr)),
+                Some(("a", "10.0.0.5"))
+            );
+        }
+
+        kw.remove_all();
+        assert!(kw.is_empty());
+        assert_eq!(kw.next(), None);
+    }
+
+    #[test]
+    fn test_remove_hand_off() {
+        let mut kw: KeyedSmoothWeight<u8, &str> = KeyedSmoothWeight::new();
+        kw.add(1, "server1", 3);
+        kw.add(2, "server2", 1);
+        kw.next();
+
+        // server2 is in the last slot, so nothing moves into its place and server1 takes its
+        // current weight
+        kw.remove(&2);
+        assert!(kw.items.iter().all(|w| w.current_weight == 0));
+
+        kw.add(2, "server2", 1);
+        let mut fresh: KeyedSmoothWeight<u8, &str> = KeyedSmoothWeight::new();
+        fresh.add(1, "server1", 3);
+        fresh.add(2, "server2", 1);
+        for _ in 0..8 {
+            assert_eq!(kw.next(), fresh.next());
+        }
+    }
+}
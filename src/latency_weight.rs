@@ -0,0 +1,251 @@
+use super::Weight;
+use rand::prelude::{Rng, ThreadRng};
+use std::time::Duration;
+
+// latencies are floored at 1µs so a zero measurement can't divide by zero
+const MIN_LATENCY_SECS: f64 = 1e-6;
+
+#[derive(Clone, Debug)]
+struct LatencyWeightItem<T> {
+    item: T,
+    weight: isize,
+    ewma: Option<f64>,
+}
+
+/// LatencyWeight adapts selection to measured response times: it keeps an exponentially weighted
+/// moving average (EWMA) of each item's latency and selects items at random in proportion to
+/// `weight / ewma`, so a backend twice as slow gets half the traffic.
+///
+/// Feed measurements with `observe`. Each one moves the average by `alpha` toward the new value,
+/// so a larger `alpha` reacts faster and a smaller one smooths more. Items without a measurement
+/// yet are assumed to be as fast as the average of the measured ones, so new backends get probed.
+/// Items with a non-positive weight are never selected.
+#[derive(Debug, Default)]
+pub struct LatencyWeight<T, R = ThreadRng> {
+    items: Vec<LatencyWeightItem<T>>,
+    alpha: f64,
+    r: R,
+}
+
+impl<T: Clone> LatencyWeight<T> {
+    /// creates a balancer with smoothing factor `alpha`, clamped to `(0, 1]`.
+    pub fn new(alpha: f64) -> Self {
+        LatencyWeight::with_rng(alpha, rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> LatencyWeight<T, R> {
+    /// creates a balancer with smoothing factor `alpha` that draws from `rng`.
+    pub fn with_rng(alpha: f64, rng: R) -> Self {
+        let alpha = if alpha.is_nan() {
+            1.0
+        } else {
+            alpha.clamp(f64::MIN_POSITIVE, 1.0)
+        };
+
+        LatencyWeight {
+            items: Vec::new(),
+            alpha,
+            r: rng,
+        }
+    }
+
+    /// records a measured latency for `item`, returning false if it isn't present.
+    pub fn observe(&mut self, item: &T, latency: Duration) -> bool
+    where
+        T: PartialEq,
+    {
+        let alpha = self.alpha;
+        let latency = latency.as_secs_f64().max(MIN_LATENCY_SECS);
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.ewma = Some(match w.ewma {
+                    Some(ewma) => ewma + alpha * (latency - ewma),
+                    None => latency,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// returns the average latency of `item`, or `None` if it isn't present or hasn't been
+    /// measured yet.
+    pub fn latency(&self, item: &T) -> Option<Duration>
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .and_then(|w| w.ewma)
+            // an average of huge measurements can be beyond what a `Duration` holds
+            .map(|ewma| Duration::try_from_secs_f64(ewma).unwrap_or(Duration::MAX))
+    }
+
+    // returns the latency assumed for unmeasured items and the sum of the selection weights
+    fn selection_totals(&self) -> (f64, f64) {
+        let (mut latency_sum, mut measured) = (0.0, 0usize);
+        let (mut measured_total, mut unmeasured_weight) = (0.0, 0.0);
+        for w in &self.items {
+            if let Some(ewma) = w.ewma {
+                latency_sum += ewma;
+                measured += 1;
+            }
+            if w.weight > 0 {
+                match w.ewma {
+                    Some(ewma) => measured_total += w.weight as f64 / ewma,
+                    None => unmeasured_weight += w.weight as f64,
+                }
+            }
+        }
+
+        let default = if measured == 0 {
+            1.0
+        } else {
+            latency_sum / measured as f64
+        };
+        (default, measured_total + unmeasured_weight / default)
+    }
+}
+
+impl<T: Clone, R: Rng> Weight for LatencyWeight<T, R> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(LatencyWeightItem {
+            item,
+            weight,
+            ewma: None,
+        });
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+    }
+
+    /// forgets every latency measurement.
+    fn reset(&mut self) {
+        for w in &mut self.items {
+            w.ewma = None;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for LatencyWeight<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let (default, total) = self.selection_totals();
+        if !(total > 0.0 && total.is_finite()) {
+            return None;
+        }
+
+        let mut point = self.r.gen_range(0.0..total);
+        let mut last = None;
+        for (i, w) in self.items.iter().enumerate() {
+            if w.weight <= 0 {
+                continue;
+            }
+            let w = w.weight as f64 / w.ewma.unwrap_or(default);
+            if point < w {
+                return Some(self.items[i].item.clone());
+            }
+            point -= w;
+            last = Some(i);
+        }
+
+        // rounding can leave `point` just past the last positive weight
+        last.map(|i| self.items[i].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LatencyWeight, Weight};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_latency_weight() {
+        let mut lw: LatencyWeight<&str> = LatencyWeight::new(0.5);
+        lw.add("fast", 1);
+        lw.add("slow", 1);
+        lw.add("new", 1);
+        lw.add("off", 0);
+
+        lw.observe(&"fast", Duration::from_millis(10));
+        lw.observe(&"slow", Duration::from_millis(50));
+        lw.observe(&"slow", Duration::from_millis(30));
+        assert_eq!(lw.latency(&"slow"), Some(Duration::from_millis(40)));
+        assert_eq!(lw.latency(&"new"), None);
+
+        // weights are 1/10 : 1/40 : 1/25 (the average), i.e. 20 : 5 : 8
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..33_000 {
+            *results.entry(lw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("off"), None);
+        assert!((19_000..21_000).contains(&results["fast"]));
+        assert!((4_500..5_500).contains(&results["slow"]));
+        assert!((7_400..8_600).contains(&results["new"]));
+
+        lw.reset();
+        assert_eq!(lw.latency(&"fast"), None);
+
+        // an average past what a Duration holds saturates instead of panicking
+        lw.observe(&"fast", Duration::MAX);
+        assert_eq!(lw.latency(&"fast"), Some(Duration::MAX));
+        assert!(lw.next().is_some());
+    }
+}
@@ -23,25 +23,1428 @@
 //!     }
 //! ```
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "random")]
+pub mod alias_weight;
+pub mod async_weight;
+#[cfg(feature = "std")]
+pub mod atomic_roundrobin;
+pub mod block_roundrobin;
+pub mod consistent_hash_weight;
+pub mod error;
+#[cfg(feature = "random")]
+pub mod fenwick_weight;
+#[cfg(feature = "random")]
+pub mod float_random_weight;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+pub mod handle_smooth_weight;
+#[cfg(feature = "std")]
+pub mod indexed_smooth_weight;
+pub mod interleave;
+#[cfg(feature = "random")]
+pub mod jittered_roundrobin;
+#[cfg(feature = "std")]
+pub mod keyed_smooth_weight;
+#[cfg(feature = "random")]
+pub mod latency_weight;
+pub mod observed_weight;
+#[cfg(feature = "random")]
+pub mod p2c_weight;
+pub mod planned_weight;
+#[cfg(feature = "random")]
 pub mod random_weight;
+pub mod range_weight;
+#[cfg(feature = "std")]
+pub mod read_mostly_weight;
+mod rotation;
 pub mod roundrobin_weight;
+#[cfg(feature = "std")]
+pub mod shared_weight;
+#[cfg(feature = "std")]
+pub mod simulation;
 pub mod smooth_weight;
+#[cfg(feature = "std")]
+pub mod throttled_weight;
+pub mod tiered_weight;
+pub mod traced_weight;
+#[cfg(feature = "tokio")]
+pub mod watched_weight;
 
+#[cfg(feature = "random")]
+pub use alias_weight::*;
+pub use async_weight::*;
+#[cfg(feature = "std")]
+pub use atomic_roundrobin::*;
+pub use block_roundrobin::*;
+pub use consistent_hash_weight::*;
+pub use error::*;
+#[cfg(feature = "random")]
+pub use fenwick_weight::*;
+#[cfg(feature = "random")]
+pub use float_random_weight::*;
+pub use handle_smooth_weight::*;
+#[cfg(feature = "std")]
+pub use indexed_smooth_weight::*;
+pub use interleave::*;
+#[cfg(feature = "random")]
+pub use jittered_roundrobin::*;
+#[cfg(feature = "std")]
+pub use keyed_smooth_weight::*;
+#[cfg(feature = "random")]
+pub use latency_weight::*;
+pub use observed_weight::*;
+#[cfg(feature = "random")]
+pub use p2c_weight::*;
+pub use planned_weight::*;
+#[cfg(feature = "random")]
 pub use random_weight::*;
+pub use range_weight::*;
+#[cfg(feature = "std")]
+pub use read_mostly_weight::*;
 pub use roundrobin_weight::*;
+#[cfg(feature = "std")]
+pub use shared_weight::*;
+#[cfg(feature = "std")]
+pub use simulation::*;
 pub use smooth_weight::*;
+#[cfg(feature = "std")]
+pub use throttled_weight::*;
+pub use tiered_weight::*;
+pub use traced_weight::*;
+#[cfg(feature = "tokio")]
+pub use watched_weight::*;
+
+use alloc::{boxed::Box, collections::BinaryHeap, vec::Vec};
+use core::{cmp::Reverse, fmt};
+#[cfg(feature = "std")]
+use std::{collections::HashMap, hash::Hash};
+
+/// how many items the `Display` impls list unless a precision is given, e.g. `{:.3}`.
+const DISPLAY_LIMIT: usize = 10;
+
+/// writes a one-line summary like `SmoothWeight{items=3, total=10, [a:5 b:2 c:3]}`.
+fn display_summary<'a, T, I>(f: &mut fmt::Formatter<'_>, name: &str, items: I) -> fmt::Result
+where
+    T: fmt::Display + 'a,
+    I: ExactSizeIterator<Item = (&'a T, isize)> + Clone,
+{
+    let limit = f.precision().unwrap_or(DISPLAY_LIMIT);
+    let total: isize = items.clone().map(|(_, w)| w).sum();
+    write!(f, "{}{{items={}, total={}, [", name, items.len(), total)?;
+    for (i, (item, weight)) in items.clone().enumerate() {
+        if i == limit {
+            write!(f, " ...")?;
+            break;
+        }
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}:{}", item, weight)?;
+    }
+    write!(f, "]}}")
+}
+
+/// returns true if both sides hold the same `(item, weight)` pairs, in any order. Only
+/// `PartialEq` is available, so each pair is matched by a scan, making this O(n²).
+fn same_items<'a, T, A, B>(a: A, b: B) -> bool
+where
+    T: PartialEq + 'a,
+    A: ExactSizeIterator<Item = (&'a T, isize)>,
+    B: ExactSizeIterator<Item = (&'a T, isize)>,
+{
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut unmatched: Vec<Option<(&T, isize)>> = b.map(Some).collect();
+    a.into_iter().all(
+        |pair| match unmatched.iter_mut().find(|other| **other == Some(pair)) {
+            Some(other) => {
+                *other = None;
+                true
+            }
+            None => false,
+        },
+    )
+}
 
 /// A common trait for weight algorithm.
+///
+/// The trait is object safe, so balancers can be stored as `Box<dyn Weight<Item = T>>`. Methods
+/// that return `impl Iterator` are only available on concrete types.
+///
+/// The balancers are not fused iterators: `next()` returns `None` while there is nothing to
+/// select, but `Some` again once items are added, weights raised or capacity released. An empty
+/// balancer keeps returning `None` without changing any state. Use `.fuse()` to stop at the first
+/// `None`.
 pub trait Weight: Iterator {
     /// adds a weighted item for selection.
+    ///
+    /// The weight is not validated: items with a zero or negative weight are stored but never
+    /// selected. Use `try_add` to reject them instead. Once the balancer holds `limit()` items,
+    /// further items are dropped; `try_add` reports that as an error.
     fn add(&mut self, item: Self::Item, weight: isize);
 
+    /// adds a weighted item for selection, or returns an error without changing anything if the
+    /// weight is not positive or the balancer is already at its `limit()`.
+    fn try_add(&mut self, item: Self::Item, weight: isize) -> Result<(), WeightError> {
+        if weight < 0 {
+            return Err(WeightError::NegativeWeight(weight));
+        }
+        if weight == 0 {
+            return Err(WeightError::ZeroWeight);
+        }
+        if self.limit().is_some_and(|max| self.len() >= max) {
+            return Err(WeightError::CapacityExceeded);
+        }
+
+        self.add(item, weight);
+        Ok(())
+    }
+
+    /// removes a weighted item and returns it with its weight, or `None` if it isn't present.
+    fn remove(&mut self, item: &Self::Item) -> Option<(Self::Item, isize)>
+    where
+        Self::Item: PartialEq;
+
+    /// removes a weighted item and returns it with its weight, or `ItemNotFound` if it isn't
+    /// present.
+    fn try_remove(&mut self, item: &Self::Item) -> Result<(Self::Item, isize), WeightError>
+    where
+        Self::Item: PartialEq,
+    {
+        self.remove(item).ok_or(WeightError::ItemNotFound)
+    }
+
+    /// changes the weight of an existing item in place, returning false if it isn't present.
+    fn update_weight(&mut self, item: &Self::Item, weight: isize) -> bool
+    where
+        Self::Item: PartialEq;
+
+    /// multiplies every weight by `factor`, saturating at the bounds of `isize`, e.g. to make room
+    /// for finer adjustments of single items. A factor of 0 leaves nothing selectable, and a
+    /// negative one makes negative weights positive.
+    ///
+    /// By default the items are drained and added back, which restarts the rotation.
+    /// `SmoothWeight`, `RoundrobinWeight` and `RandWeight` scale in place instead.
+    fn scale_weights(&mut self, factor: isize)
+    where
+        Self: Sized,
+    {
+        let items: Vec<_> = self.drain().collect();
+        for (item, weight) in items {
+            self.add(item, weight.saturating_mul(factor));
+        }
+    }
+
+    /// exchanges the weights of `a` and `b`, e.g. to move traffic from an old backend to a new
+    /// one in a single step. Returns false without changing anything if either isn't present.
+    fn swap_weights(&mut self, a: &Self::Item, b: &Self::Item) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        let weight_of = |item: &Self::Item| {
+            (0..self.len())
+                .filter_map(|i| self.get(i))
+                .find(|(i, _)| *i == item)
+                .map(|(_, w)| w)
+        };
+        let (wa, wb) = match (weight_of(a), weight_of(b)) {
+            (Some(wa), Some(wb)) => (wa, wb),
+            _ => return false,
+        };
+
+        self.update_weight(a, wb);
+        self.update_weight(b, wa);
+        true
+    }
+
+    /// replaces `old` with `new`, keeping its weight, and returns false if `old` isn't present.
+    ///
+    /// The default removes `old` and adds `new`, which loses its place in the rotation.
+    /// `SmoothWeight`, `RoundrobinWeight`, `RandWeight` and `IndexedSmoothWeight` swap the value in
+    /// place so the accumulated selection state carries over.
+    fn replace(&mut self, old: &Self::Item, new: Self::Item) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        match self.remove(old) {
+            Some((_, weight)) => {
+                self.add(new, weight);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// returns all items.
-    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_;
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_
+    where
+        Self: Sized;
+
+    /// returns all items by reference with their weights, like `all` but without cloning them.
+    fn iter(&self) -> impl Iterator<Item = (&Self::Item, isize)> + '_
+    where
+        Self: Sized,
+    {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
 
-    /// removes all weighted items.
+    /// removes all weighted items and releases their storage. Settings that don't depend on the
+    /// items, such as a random generator, stay as they are.
     fn remove_all(&mut self);
 
+    /// removes all weighted items like `remove_all`, but keeps the allocated storage for a refill
+    /// of similar size. By contrast `reset` keeps the items and only restarts the selection.
+    ///
+    /// The default just calls `remove_all`.
+    fn clear_items(&mut self) {
+        self.remove_all();
+    }
+
+    /// adds every item of `other`. An item that is already present has `other`'s weight added
+    /// to its own, saturating at `isize::MAX`, instead of being added again.
+    fn merge(&mut self, other: &Self)
+    where
+        Self: Sized,
+        Self::Item: PartialEq,
+    {
+        for (item, weight) in other.all() {
+            let existing = self.iter().find(|(i, _)| **i == item).map(|(_, w)| w);
+            match existing {
+                Some(w) => {
+                    self.update_weight(&item, w.saturating_add(weight));
+                }
+                None => self.add(item, weight),
+            }
+        }
+    }
+
+    /// keeps only the items for which `f(item, weight)` returns true, removing the rest in one
+    /// pass.
+    ///
+    /// The default decides per item rather than per value, so of two equal items only the ones
+    /// `f` rejects go. If any do, it refills the balancer with the kept items in their order,
+    /// which restarts the selection.
+    fn retain<F: FnMut(&Self::Item, isize) -> bool>(&mut self, mut f: F)
+    where
+        Self: Sized,
+    {
+        let kept: Vec<(Self::Item, isize)> = Weight::all(self)
+            .filter(|(item, weight)| f(item, *weight))
+            .collect();
+        if kept.len() == self.len() {
+            return;
+        }
+
+        self.clear_items();
+        for (item, weight) in kept {
+            self.add(item, weight);
+        }
+    }
+
+    /// removes every item and yields it with its weight, leaving the balancer empty and ready for
+    /// reuse.
+    fn drain(&mut self) -> impl Iterator<Item = (Self::Item, isize)> + '_
+    where
+        Self: Sized,
+    {
+        let items: Vec<_> = Weight::all(self).collect();
+        self.remove_all();
+        items.into_iter()
+    }
+
     /// resets the balancing algorithm.
     fn reset(&mut self);
+
+    /// returns the number of items.
+    fn len(&self) -> usize;
+
+    /// returns the sum of the positive weights, saturating at `isize::MAX`. Items with a zero or
+    /// negative weight add nothing, as they are never selected.
+    ///
+    /// This and `min_weight`/`max_weight` walk the items through `get` rather than `iter`, so
+    /// they stay callable on a `dyn Weight`. A balancer whose `get` isn't O(1) should override
+    /// all three to walk its storage directly.
+    fn total_weight(&self) -> isize {
+        let total: i128 = (0..self.len())
+            .filter_map(|i| self.get(i))
+            .map(|(_, weight)| weight.max(0) as i128)
+            .sum();
+        total.min(isize::MAX as i128) as isize
+    }
+
+    /// returns the smallest configured weight, or `None` if there are no items.
+    fn min_weight(&self) -> Option<isize> {
+        (0..self.len())
+            .filter_map(|i| self.get(i))
+            .map(|(_, weight)| weight)
+            .min()
+    }
+
+    /// returns the largest configured weight, or `None` if there are no items.
+    fn max_weight(&self) -> Option<isize> {
+        (0..self.len())
+            .filter_map(|i| self.get(i))
+            .map(|(_, weight)| weight)
+            .max()
+    }
+
+    /// returns the most items the balancer accepts, or `None` if it is unbounded.
+    fn limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// returns the item in slot `index` with its weight, or `None` if `index` is out of bounds.
+    /// Slots follow insertion order until items are removed; removal may move another item into
+    /// the freed slot.
+    fn get(&self, index: usize) -> Option<(&Self::Item, isize)>;
+
+    /// reserves room for at least `additional` more items, ahead of a known burst of `add` calls.
+    /// This is only a hint; the default does nothing.
+    fn reserve(&mut self, _additional: usize) {}
+
+    /// returns true if there are no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns true if `item` is present.
+    fn contains(&self, item: &Self::Item) -> bool
+    where
+        Self::Item: PartialEq;
+
+    /// returns true if `item` is present with exactly `weight`.
+    fn contains_with_weight(&self, item: &Self::Item, weight: isize) -> bool
+    where
+        Self: Sized,
+        Self::Item: PartialEq,
+    {
+        self.all().any(|(i, w)| i == *item && w == weight)
+    }
+
+    /// selects the next item like `next()`, but says why nothing could be selected: `Empty` if
+    /// there are no items and `ZeroTotalWeight` if none of them can be drawn.
+    fn try_next(&mut self) -> Result<Self::Item, WeightError> {
+        if self.is_empty() {
+            return Err(WeightError::Empty);
+        }
+        self.next().ok_or(WeightError::ZeroTotalWeight)
+    }
+
+    /// returns `prev` again if it is still present and `healthy`, otherwise selects the next item
+    /// as usual. This keeps reusing a connection until its backend goes away or turns unhealthy.
+    fn next_with_affinity(
+        &mut self,
+        prev: Option<&Self::Item>,
+        healthy: &dyn Fn(&Self::Item) -> bool,
+    ) -> Option<Self::Item>
+    where
+        Self: Sized,
+        Self::Item: PartialEq + Clone,
+    {
+        if let Some(prev) = prev {
+            if healthy(prev) && self.contains(prev) {
+                return Some(prev.clone());
+            }
+        }
+
+        self.next()
+    }
+
+    #[cfg(feature = "std")]
+    /// draws `samples` selections and reports, per item, how often it was picked and its longest
+    /// run of consecutive picks. The run length shows how bursty an algorithm is.
+    fn simulate(&mut self, samples: usize) -> Simulation<Self::Item>
+    where
+        Self: Sized,
+        Self::Item: Eq + Hash + Clone,
+    {
+        Simulation::from_selections(self.take(samples))
+    }
+
+    /// returns each item's weight as a fraction of the total weight. Items with a non-positive
+    /// weight get 0, and so does every item if no weight is positive.
+    fn expected_ratios(&self) -> Vec<(Self::Item, f64)>
+    where
+        Self: Sized,
+    {
+        let total: i128 = self.all().map(|(_, w)| w.max(0) as i128).sum();
+        self.all()
+            .map(|(item, w)| {
+                let ratio = if total > 0 {
+                    w.max(0) as f64 / total as f64
+                } else {
+                    0.0
+                };
+                (item, ratio)
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "std")]
+    /// draws `n` selections and counts how often each item was picked, for comparing against
+    /// `expected_ratios`.
+    fn sample(&mut self, n: usize) -> HashMap<Self::Item, usize>
+    where
+        Self: Sized,
+        Self::Item: Eq + Hash,
+    {
+        let mut counts = HashMap::new();
+        for item in self.take(n) {
+            *counts.entry(item).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[cfg(feature = "std")]
+    /// draws `samples` selections and returns the largest relative error between how often an
+    /// item was picked and its `expected_ratios` share, e.g. 0.05 if some item was off by 5% of
+    /// its expected count. A perfectly fair run gives 0. Selecting an item whose expected share is
+    /// 0 gives infinity, and drawing nothing gives 0.
+    fn fairness(&mut self, samples: usize) -> f64
+    where
+        Self: Sized,
+        Self::Item: Eq + Hash,
+    {
+        let mut expected: HashMap<Self::Item, f64> = HashMap::new();
+        for (item, ratio) in self.expected_ratios() {
+            *expected.entry(item).or_insert(0.0) += ratio;
+        }
+
+        let counts = self.sample(samples);
+        if samples == 0 {
+            return 0.0;
+        }
+
+        let observed =
+            |item: &Self::Item| counts.get(item).map_or(0, |&c| c) as f64 / samples as f64;
+        let mut worst: f64 = 0.0;
+        for (item, ratio) in &expected {
+            if *ratio > 0.0 {
+                worst = worst.max((observed(item) - ratio).abs() / ratio);
+            }
+        }
+        for item in counts.keys() {
+            if expected.get(item).is_none_or(|&ratio| ratio <= 0.0) {
+                return f64::INFINITY;
+            }
+        }
+        worst
+    }
+
+    /// selects the next item for which `pred` returns true, letting the rest sit out without
+    /// removing them. Returns `None` if nothing passes.
+    ///
+    /// By default this draws at most as many selections as there are items, so an item that keeps
+    /// coming up can exhaust the attempts. `SmoothWeight`, `RoundrobinWeight` and `RandWeight`
+    /// skip rejected items inside the algorithm instead.
+    fn next_filtered<F: FnMut(&Self::Item) -> bool>(&mut self, mut pred: F) -> Option<Self::Item>
+    where
+        Self: Sized,
+    {
+        for _ in 0..self.len() {
+            let item = self.next()?;
+            if pred(&item) {
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    /// selects the next item that isn't in `excluded`, e.g. the backends a request has already
+    /// failed on, or `None` if every item is excluded. Works like `next_filtered`: with
+    /// `SmoothWeight` the excluded items sit the round out, so their current weights don't grow
+    /// while they are skipped and they don't get a burst of selections afterwards.
+    fn next_excluding(&mut self, excluded: &[Self::Item]) -> Option<Self::Item>
+    where
+        Self: Sized,
+        Self::Item: PartialEq,
+    {
+        self.next_filtered(|item| !excluded.contains(item))
+    }
+
+    /// selects the next item, or returns `default` if nothing can be selected, e.g. to fall back
+    /// to a local backend.
+    fn next_or(&mut self, default: Self::Item) -> Self::Item {
+        self.next().unwrap_or(default)
+    }
+
+    /// selects the next item, or returns what `f` computes if nothing can be selected. `f` is
+    /// only called in that case.
+    fn next_or_else<F: FnOnce() -> Self::Item>(&mut self, f: F) -> Self::Item
+    where
+        Self: Sized,
+    {
+        self.next().unwrap_or_else(f)
+    }
+
+    /// returns an iterator over the next `n` selections, borrowing the balancer, so they can be
+    /// mapped and collected like any bounded iterator. It ends early only if nothing can be
+    /// selected.
+    fn take_weighted(&mut self, n: usize) -> impl Iterator<Item = Self::Item> + '_
+    where
+        Self: Sized,
+    {
+        self.by_ref().take(n)
+    }
+
+    /// selects `n` items, skipping any `None`s, so an empty balancer gives an empty vec.
+    fn next_n(&mut self, n: usize) -> Vec<Self::Item> {
+        (0..n).filter_map(|_| self.next()).collect()
+    }
+
+    /// selects `count` items and passes each to `f` without collecting them.
+    fn for_each_selection<F: FnMut(&Self::Item)>(&mut self, count: usize, mut f: F)
+    where
+        Self: Sized,
+    {
+        for _ in 0..count {
+            match self.next() {
+                Some(item) => f(&item),
+                None => break,
+            }
+        }
+    }
+
+    /// splits the items into `n` groups whose total weights are as even as possible, using the
+    /// greedy longest-processing-time heuristic: the heaviest remaining item always goes to the
+    /// lightest group. (Named to stay clear of `Iterator::partition`.)
+    fn weighted_partition(&self, n: usize) -> Vec<Vec<(Self::Item, isize)>>
+    where
+        Self: Sized,
+    {
+        let mut groups: Vec<Vec<(Self::Item, isize)>> = (0..n).map(|_| Vec::new()).collect();
+        if n == 0 {
+            return groups;
+        }
+
+        let mut items: Vec<(Self::Item, isize)> = self.all().collect();
+        items.sort_by_key(|(_, w)| Reverse(*w));
+
+        let mut totals: BinaryHeap<Reverse<(i128, usize)>> =
+            (0..n).map(|i| Reverse((0, i))).collect();
+        for (item, weight) in items {
+            if let Some(Reverse((total, i))) = totals.pop() {
+                groups[i].push((item, weight));
+                totals.push(Reverse((total + weight as i128, i)));
+            }
+        }
+
+        groups
+    }
+}
+
+/// resets every balancer in `selectors`.
+pub fn reset_all<W: Weight>(selectors: &mut [W]) {
+    for w in selectors {
+        w.reset();
+    }
+}
+
+/// resets every type-erased balancer in `selectors`.
+pub fn reset_all_dyn<T>(selectors: &mut [Box<dyn Weight<Item = T>>]) {
+    for w in selectors {
+        w.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "random")]
+    use crate::RandWeight;
+    use crate::{
+        reset_all, reset_all_dyn, BlockRoundrobin, HandleSmoothWeight, RoundrobinWeight,
+        SmoothWeight, TracedWeight, Weight, WeightError,
+    };
+    use std::collections::HashMap;
+
+    // one of each core balancer, for the tests that hold for all of them
+    fn balancers<T: Clone + 'static>() -> Vec<Box<dyn Weight<Item = T>>> {
+        vec![
+            Box::new(SmoothWeight::new()),
+            Box::new(RoundrobinWeight::new()),
+            #[cfg(feature = "random")]
+            Box::new(RandWeight::new()),
+        ]
+    }
+
+    #[test]
+    fn test_reset_all() {
+        let mut selectors: Vec<SmoothWeight<&str>> = (0..3)
+            .map(|_| {
+                let mut sw = SmoothWeight::new();
+                sw.add("server1", 5);
+                sw.add("server2", 2);
+                sw
+            })
+            .collect();
+        let initial: Vec<&str> = selectors[0].by_ref().take(7).collect();
+        selectors[0].reset();
+
+        for (i, sw) in selectors.iter_mut().enumerate() {
+            sw.by_ref().take(i + 1).for_each(drop);
+        }
+        reset_all(&mut selectors);
+        for sw in &mut selectors {
+            assert_eq!(sw.by_ref().take(7).collect::<Vec<_>>(), initial);
+        }
+
+        let mut sw = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        let mut rrw = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        let mut boxed: Vec<Box<dyn Weight<Item = &str>>> = vec![Box::new(sw), Box::new(rrw)];
+        let initial: Vec<Vec<&str>> = boxed
+            .iter_mut()
+            .map(|w| w.by_ref().take(7).collect())
+            .collect();
+
+        for w in &mut boxed {
+            w.next();
+        }
+        reset_all_dyn(&mut boxed);
+        for (w, initial) in boxed.iter_mut().zip(initial) {
+            assert_eq!(w.by_ref().take(7).collect::<Vec<_>>(), initial);
+        }
+    }
+
+    #[test]
+    fn test_for_each_selection() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        rrw.add("server3", 3);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        rrw.for_each_selection(100, |s| *results.entry(*s).or_insert(0) += 1);
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server2"], 20);
+        assert_eq!(results["server3"], 30);
+
+        let mut calls = 0;
+        RoundrobinWeight::<&str>::new().for_each_selection(10, |_| calls += 1);
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn test_weighted_partition() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("a", 7);
+        sw.add("b", 5);
+        sw.add("c", 4);
+        sw.add("d", 3);
+        sw.add("e", 1);
+
+        let groups = sw.weighted_partition(2);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0], vec![("a", 7), ("d", 3)]);
+        assert_eq!(groups[1], vec![("b", 5), ("c", 4), ("e", 1)]);
+
+        assert_eq!(sw.weighted_partition(0).len(), 0);
+        assert_eq!(
+            sw.weighted_partition(7)
+                .iter()
+                .filter(|g| g.is_empty())
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_len_and_contains() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        assert_eq!(sw.len(), 0);
+        assert!(sw.is_empty());
+
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        assert_eq!(sw.len(), 2);
+        assert!(!sw.is_empty());
+        assert!(sw.contains(&"server1"));
+        assert!(!sw.contains(&"server3"));
+
+        sw.remove(&"server1");
+        assert_eq!(sw.len(), 1);
+        assert!(!sw.contains(&"server1"));
+        sw.remove_all();
+        assert!(sw.is_empty());
+    }
+
+    #[test]
+    fn test_try_add() {
+        let mut selectors = balancers::<&'static str>();
+
+        for w in &mut selectors {
+            assert_eq!(
+                w.try_add("server1", -1),
+                Err(WeightError::NegativeWeight(-1))
+            );
+            assert_eq!(w.try_add("server1", 0), Err(WeightError::ZeroWeight));
+            assert!(w.is_empty());
+
+            assert_eq!(w.try_add("server1", 3), Ok(()));
+            assert_eq!(w.len(), 1);
+            assert_eq!(w.next(), Some("server1"));
+        }
+    }
+
+    #[test]
+    fn test_into_items() {
+        let expected = vec![("a".to_string(), 3), ("b".to_string(), 1)];
+
+        let mut sw = SmoothWeight::new();
+        let mut rr = RoundrobinWeight::new();
+        for (item, weight) in &expected {
+            sw.add(item.clone(), *weight);
+            rr.add(item.clone(), *weight);
+        }
+        assert_eq!(sw.into_items().collect::<Vec<_>>(), expected);
+        assert_eq!(rr.into_items().collect::<Vec<_>>(), expected);
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw = RandWeight::new();
+            for (item, weight) in &expected {
+                rw.add(item.clone(), *weight);
+            }
+            assert_eq!(rw.into_items().collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_expected_ratios_and_sample() {
+        let mut sw = SmoothWeight::new();
+        sw.add("a", 5);
+        sw.add("b", 3);
+        sw.add("c", 2);
+        sw.add("d", 0);
+
+        let ratios = sw.expected_ratios();
+        assert_eq!(ratios, vec![("a", 0.5), ("b", 0.3), ("c", 0.2), ("d", 0.0)]);
+
+        // smooth weighting is exact over whole cycles
+        let counts = sw.sample(1000);
+        for (item, ratio) in ratios {
+            let expected = (ratio * 1000.0) as usize;
+            assert_eq!(counts.get(&item).copied().unwrap_or(0), expected);
+        }
+    }
+
+    #[test]
+    fn test_next_n() {
+        let mut selectors = balancers::<&'static str>();
+
+        for w in selectors.iter_mut() {
+            assert!(w.next_n(5).is_empty());
+
+            w.add("server1", 3);
+            w.add("server2", 1);
+            let batch = w.next_n(8);
+            assert_eq!(batch.len(), 8);
+            assert!(batch.iter().all(|i| *i == "server1" || *i == "server2"));
+        }
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut selectors: Vec<Box<dyn Weight<Item = usize>>> = vec![
+            Box::new(SmoothWeight::with_capacity(100)),
+            Box::new(RoundrobinWeight::with_capacity(100)),
+            #[cfg(feature = "random")]
+            Box::new(RandWeight::with_capacity(100)),
+        ];
+
+        for w in selectors.iter_mut() {
+            assert!(w.is_empty());
+            for i in 0..100 {
+                w.add(i, 1);
+            }
+            assert_eq!(w.len(), 100);
+            assert!(w.next().is_some());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_retain() {
+        fn check<W: Weight<Item = &'static str>>(mut w: W, exact: bool) {
+            w.add("server1", 1);
+            w.add("server2", 5);
+            w.add("server3", 2);
+            w.add("server4", 3);
+            for _ in 0..7 {
+                w.next();
+            }
+
+            w.retain(|_, weight| weight > 2);
+            assert_eq!(
+                Weight::all(&w).collect::<Vec<_>>(),
+                vec![("server2", 5), ("server4", 3)]
+            );
+
+            let counts = w.sample(8000);
+            assert_eq!(counts.len(), 2);
+            if exact {
+                assert_eq!(counts["server2"], 5000);
+                assert_eq!(counts["server4"], 3000);
+            } else {
+                assert!(counts["server2"] > counts["server4"]);
+            }
+        }
+
+        check(SmoothWeight::new(), true);
+        check(RoundrobinWeight::new(), true);
+        #[cfg(feature = "random")]
+        check(RandWeight::new(), false);
+    }
+
+    #[test]
+    fn test_retain_duplicates() {
+        // the default retain keeps the heavier of two equal items, not the first one
+        let mut bw: BlockRoundrobin<&str> = BlockRoundrobin::new();
+        bw.add("server1", 1);
+        bw.add("server2", 2);
+        bw.add("server1", 3);
+        bw.next();
+        bw.retain(|_, weight| weight > 2);
+        assert_eq!(Weight::all(&bw).collect::<Vec<_>>(), vec![("server1", 3)]);
+        assert_eq!(bw.next_n(3), vec!["server1"; 3]);
+
+        bw.retain(|_, _| true);
+        assert_eq!(bw.len(), 1);
+    }
+
+    #[test]
+    fn test_drain() {
+        fn check<W: Weight<Item = &'static str>>(mut w: W) {
+            w.add("server1", 2);
+            w.add("server2", 1);
+            w.next();
+
+            let drained: Vec<_> = w.drain().collect();
+            assert_eq!(drained, vec![("server1", 2), ("server2", 1)]);
+            assert!(w.is_empty());
+            assert_eq!(w.next(), None);
+
+            w.add("server3", 1);
+            assert_eq!(w.next(), Some("server3"));
+        }
+
+        check(SmoothWeight::new());
+        check(RoundrobinWeight::new());
+        #[cfg(feature = "random")]
+        check(RandWeight::new());
+        #[cfg(feature = "random")]
+        check(crate::AliasWeight::new());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut selectors = balancers::<&'static str>();
+        #[cfg(feature = "std")]
+        selectors.push(Box::new(crate::IndexedSmoothWeight::new()));
+        #[cfg(feature = "random")]
+        selectors.push(Box::new(crate::AliasWeight::new()));
+
+        for w in selectors.iter_mut() {
+            w.add("server1", 3);
+            w.add("server2", 1);
+            assert!(w.replace(&"server1", "server3"));
+            assert!(!w.replace(&"server1", "server4"));
+            assert!(!w.contains(&"server1"));
+            assert!(w.contains(&"server3"));
+            assert_eq!(w.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_from_iter() {
+        use std::iter::FromIterator;
+
+        let weights = vec![("a", 4), ("b", 2)];
+
+        let mut sw: SmoothWeight<&str> = weights.iter().copied().collect();
+        assert_eq!(sw.next_n(3), vec!["a", "b", "a"]);
+
+        let mut rr = RoundrobinWeight::from_iter(weights.clone());
+        assert_eq!(rr.next_n(3), vec!["a", "a", "b"]);
+
+        #[cfg(feature = "random")]
+        {
+            let rw = RandWeight::from_iter(weights.clone());
+            assert_eq!(Weight::all(&rw).collect::<Vec<_>>(), weights);
+            assert_eq!(rw.expected_ratios()[0].1 * 3.0, 2.0);
+        }
+    }
+
+    #[test]
+    fn test_try_next() {
+        let mut selectors = balancers::<&'static str>();
+
+        for w in selectors.iter_mut() {
+            assert_eq!(w.try_next(), Err(WeightError::Empty));
+            assert_eq!(w.try_remove(&"server1"), Err(WeightError::ItemNotFound));
+
+            w.add("server1", 2);
+            assert_eq!(w.try_next(), Ok("server1"));
+            assert_eq!(w.try_remove(&"server1"), Ok(("server1", 2)));
+        }
+
+        let mut rr = RoundrobinWeight::new();
+        rr.add("server1", 0);
+        rr.add("server2", 0);
+        assert_eq!(rr.try_next(), Err(WeightError::ZeroTotalWeight));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_extend() {
+        let batch = vec![("b", 4), ("c", 6)];
+
+        let mut rr = RoundrobinWeight::new();
+        rr.add("a", 0);
+        rr.extend(batch.clone());
+        assert_eq!(rr.next_n(5), vec!["c", "b", "c", "b", "c"]);
+        rr.extend(vec![("d", 3)]);
+        assert_eq!(rr.len(), 4);
+        let counts = rr.sample(1300);
+        assert_eq!(counts["b"], 400);
+        assert_eq!(counts["d"], 300);
+
+        let mut sw = SmoothWeight::new();
+        sw.add("a", 2);
+        sw.extend(batch.clone());
+        assert_eq!(sw.sample(1200)["c"], 600);
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw = RandWeight::new();
+            rw.extend(batch);
+            assert!(rw.contains_with_weight(&"c", 6));
+            assert_eq!(rw.expected_ratios()[0].1, 0.4);
+        }
+    }
+
+    #[test]
+    fn test_reset_zeroes_counters() {
+        fn run(w: &mut dyn Weight<Item = &'static str>) {
+            w.add("server1", 1);
+            w.next_n(3);
+            w.reset();
+        }
+
+        let mut sw = SmoothWeight::new();
+        sw.enable_stats();
+        run(&mut sw);
+        assert_eq!(sw.selection_count(&"server1"), 0);
+        sw.next();
+        assert_eq!(sw.selection_count(&"server1"), 1);
+
+        let mut rr = RoundrobinWeight::new();
+        rr.enable_stats();
+        run(&mut rr);
+        assert_eq!(rr.selection_count(&"server1"), 0);
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw = RandWeight::new();
+            rw.enable_stats();
+            run(&mut rw);
+            assert_eq!(rw.selection_count(&"server1"), 0);
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let mut selectors = balancers::<&'static str>();
+
+        for w in selectors.iter_mut() {
+            assert_eq!(w.get(0), None);
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+            w.remove(&"server1");
+
+            // the last item moves into the freed slot
+            assert_eq!(w.get(0), Some((&"server3", 3)));
+            assert_eq!(w.get(1), Some((&"server2", 2)));
+            assert_eq!(w.get(2), None);
+        }
+    }
+
+    #[test]
+    fn test_zero_weight_never_selected() {
+        let mut selectors = balancers::<&'static str>();
+
+        for w in selectors.iter_mut() {
+            w.add("zero1", 0);
+            w.add("server1", 3);
+            w.add("zero2", 0);
+            w.add("server2", 1);
+
+            for _ in 0..10_000 {
+                let s = w.next().unwrap();
+                assert!(s == "server1" || s == "server2");
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_returns_item() {
+        let mut selectors = balancers::<String>();
+
+        for w in selectors.iter_mut() {
+            for (i, weight) in [3, 1, 2, 5].iter().enumerate() {
+                w.add(format!("server{}", i), *weight);
+            }
+            for _ in 0..5 {
+                w.next();
+            }
+
+            assert_eq!(
+                w.remove(&"server1".to_string()),
+                Some(("server1".to_string(), 1))
+            );
+            assert_eq!(
+                w.remove(&"server0".to_string()),
+                Some(("server0".to_string(), 3))
+            );
+            assert_eq!(w.remove(&"server0".to_string()), None);
+            for _ in 0..1000 {
+                let s = w.next().unwrap();
+                assert!(s == "server2" || s == "server3");
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_conversions() {
+        let mut sw = SmoothWeight::new();
+        sw.add("a", 6);
+        sw.add("b", 3);
+        sw.next();
+
+        let mut rr = RoundrobinWeight::from(sw);
+        assert_eq!(rr.next_n(3), vec!["a", "a", "b"]);
+        rr.next();
+
+        let mut sw = SmoothWeight::from(rr);
+        assert_eq!(sw.next_n(3), vec!["a", "b", "a"]);
+
+        #[cfg(feature = "random")]
+        {
+            let rw = RandWeight::from(sw);
+            assert_eq!(
+                rw.expected_ratios(),
+                vec![("a", 2.0 / 3.0), ("b", 1.0 / 3.0)]
+            );
+            let mut rr = RoundrobinWeight::from(rw);
+            assert_eq!(rr.next_n(3), vec!["a", "a", "b"]);
+            let rw = RandWeight::from(rr);
+            let mut sw = SmoothWeight::from(rw);
+            assert_eq!(sw.next_n(3), vec!["a", "b", "a"]);
+        }
+    }
+
+    #[test]
+    fn test_with_limit() {
+        let mut balancers: Vec<Box<dyn Weight<Item = &str>>> = vec![
+            Box::new(SmoothWeight::with_limit(2)),
+            Box::new(RoundrobinWeight::with_limit(2)),
+            #[cfg(feature = "random")]
+            Box::new(RandWeight::with_limit(2)),
+        ];
+
+        for w in balancers.iter_mut() {
+            assert_eq!(w.limit(), Some(2));
+            assert_eq!(w.try_add("server1", 1), Ok(()));
+            w.add("server2", 1);
+            assert_eq!(w.try_add("server3", 1), Err(WeightError::CapacityExceeded));
+            w.add("server3", 1);
+            assert_eq!(w.len(), 2);
+            assert!(!w.contains(&"server3"));
+
+            // items already present can still be updated
+            assert!(w.update_weight(&"server2", 3));
+            assert_eq!(w.get(1), Some((&"server2", 3)));
+
+            // removing one makes room again
+            w.remove(&"server1");
+            assert_eq!(w.try_add("server3", 1), Ok(()));
+        }
+
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::with_limit(2);
+        rrw.extend(vec![("a", 1), ("b", 1), ("c", 1)]);
+        assert_eq!(rrw.len(), 2);
+        assert_eq!(SmoothWeight::<&str>::new().limit(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fairness() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+        sw.add("server4", 0);
+
+        // a whole number of cycles is exactly fair
+        assert_eq!(sw.fairness(100), 0.0);
+        assert_eq!(sw.fairness(0), 0.0);
+
+        // a single draw picks server1, which should only get half of them
+        sw.reset();
+        assert!((sw.fairness(1) - 1.0).abs() < 1e-9);
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw: RandWeight<&str> = Weight::all(&sw).collect();
+            assert!(rw.fairness(100_000) < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_weight_extremes() {
+        let mut balancers = balancers::<&'static str>();
+
+        for w in balancers.iter_mut() {
+            assert_eq!(w.total_weight(), 0);
+            assert_eq!(w.min_weight(), None);
+            assert_eq!(w.max_weight(), None);
+
+            w.add("server1", 5);
+            w.add("server2", -2);
+            w.add("server3", 3);
+            assert_eq!(w.total_weight(), 8);
+            assert_eq!(w.min_weight(), Some(-2));
+            assert_eq!(w.max_weight(), Some(5));
+
+            w.update_weight(&"server1", 1);
+            assert_eq!(w.total_weight(), 4);
+            assert_eq!(w.max_weight(), Some(3));
+
+            w.add("server4", isize::MAX);
+            assert_eq!(w.total_weight(), isize::MAX);
+        }
+    }
+
+    #[test]
+    fn test_partial_eq() {
+        let a: SmoothWeight<&str> = vec![("server1", 5), ("server2", 2)].into_iter().collect();
+        let mut b: SmoothWeight<&str> = vec![("server2", 2), ("server1", 5)].into_iter().collect();
+        assert_eq!(a, b);
+
+        // selection state doesn't matter
+        b.next();
+        assert_eq!(a, b);
+
+        b.update_weight(&"server2", 3);
+        assert_ne!(a, b);
+        b.update_weight(&"server2", 2);
+        b.add("server1", 5);
+        assert_ne!(a, b);
+
+        let rr: RoundrobinWeight<&str> = vec![("a", 1), ("a", 2)].into_iter().collect();
+        let same: RoundrobinWeight<&str> = vec![("a", 2), ("a", 1)].into_iter().collect();
+        let other: RoundrobinWeight<&str> = vec![("a", 1), ("a", 1)].into_iter().collect();
+        assert_eq!(rr, same);
+        assert_ne!(rr, other);
+
+        #[cfg(feature = "random")]
+        {
+            let x: RandWeight<&str> = vec![("server1", 5), ("server2", 2)].into_iter().collect();
+            let y = RandWeight::from(a);
+            assert!(x == y);
+            assert!(x != RandWeight::new());
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        // the items are borrowed, not cloned
+        let mut sw: SmoothWeight<String> = SmoothWeight::new();
+        sw.add("server1".to_string(), 5);
+        sw.add("server2".to_string(), 2);
+        let items: Vec<(&String, isize)> = sw.iter().collect();
+        assert_eq!(
+            items,
+            vec![(&"server1".to_string(), 5), (&"server2".to_string(), 2)]
+        );
+
+        let mut hw: HandleSmoothWeight<&str> = HandleSmoothWeight::new();
+        let a = hw.insert("a", 1);
+        hw.insert("b", 2);
+        hw.remove_by_handle(a);
+        assert_eq!(hw.iter().collect::<Vec<_>>(), vec![(&"b", 2)]);
+
+        let tw = TracedWeight::new(sw, 1);
+        assert_eq!(tw.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_next_after_none() {
+        fn check<W: Weight<Item = &'static str>>(mut w: W) {
+            // empty, then only zero weights: None every time, without corrupting anything
+            for _ in 0..3 {
+                assert_eq!(w.next(), None);
+            }
+            w.add("server1", 0);
+            for _ in 0..3 {
+                assert_eq!(w.next(), None);
+            }
+            assert_eq!(w.by_ref().fuse().next(), None);
+            assert_eq!(w.by_ref().take_while(|_| true).count(), 0);
+
+            w.update_weight(&"server1", 2);
+            w.add("server2", 2);
+            assert_eq!(w.by_ref().fuse().take(10).count(), 10);
+            assert_eq!(w.by_ref().take_while(|_| true).take(5).count(), 5);
+            assert_eq!(w.by_ref().zip(0..4).count(), 4);
+            assert!(w
+                .by_ref()
+                .take(10)
+                .all(|s| s == "server1" || s == "server2"));
+
+            w.remove_all();
+            let mut fused = w.fuse();
+            assert_eq!(fused.next(), None);
+            assert_eq!(fused.next(), None);
+        }
+
+        check(SmoothWeight::new());
+        check(RoundrobinWeight::new());
+        #[cfg(feature = "random")]
+        check(RandWeight::new());
+    }
+
+    #[test]
+    fn test_merge() {
+        fn check<W>()
+        where
+            W: Weight<Item = &'static str> + std::iter::FromIterator<(&'static str, isize)>,
+        {
+            let mut a: W = vec![("a", 1), ("b", 2)].into_iter().collect();
+            let b: W = vec![("b", 3), ("c", 1)].into_iter().collect();
+            a.merge(&b);
+            assert_eq!(
+                a.iter().collect::<Vec<_>>(),
+                vec![(&"a", 1), (&"b", 5), (&"c", 1)]
+            );
+            assert_eq!(a.total_weight(), 7);
+            assert_eq!(a.max_weight(), Some(5));
+            assert_eq!(a.expected_ratios()[1], ("b", 5.0 / 7.0));
+        }
+
+        check::<SmoothWeight<&str>>();
+        check::<RoundrobinWeight<&str>>();
+        #[cfg(feature = "random")]
+        check::<RandWeight<&str>>();
+    }
+
+    #[test]
+    fn test_swap_weights() {
+        let mut rr: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rr.add("blue", 3);
+        rr.add("green", 1);
+        assert_eq!(rr.next_n(4), vec!["blue", "blue", "blue", "green"]);
+
+        assert!(rr.swap_weights(&"blue", &"green"));
+        assert!(!rr.swap_weights(&"green", &"red"));
+        assert!(rr.contains_with_weight(&"green", 3));
+        let picks = rr.next_n(40);
+        assert_eq!(picks.iter().filter(|s| **s == "green").count(), 30);
+    }
+
+    #[test]
+    fn test_scale_weights() {
+        // scaling by a constant doesn't change the sequence, even mid-rotation
+        let mut rr: RoundrobinWeight<&str> =
+            vec![("a", 3), ("b", 2), ("c", 1)].into_iter().collect();
+        let mut scaled = RoundrobinWeight::new();
+        scaled.extend(Weight::all(&rr));
+        rr.next_n(2);
+        scaled.next_n(2);
+        scaled.scale_weights(10);
+        assert!(scaled.contains_with_weight(&"a", 30));
+        assert_eq!(scaled.next_n(12), rr.next_n(12));
+
+        let mut sw: SmoothWeight<&str> = vec![("a", 3), ("b", 2)].into_iter().collect();
+        let mut plain = SmoothWeight::new();
+        plain.extend(Weight::all(&sw));
+        sw.next_n(3);
+        plain.next_n(3);
+        sw.scale_weights(10);
+        assert_eq!(sw.total_weight(), 50);
+        assert_eq!(sw.next_n(10), plain.next_n(10));
+
+        // the default rebuilds the items
+        let mut tw = TracedWeight::new(RoundrobinWeight::new(), 1);
+        tw.add("a", isize::MAX / 2);
+        tw.add("b", -1);
+        tw.scale_weights(3);
+        assert_eq!(tw.max_weight(), Some(isize::MAX));
+        assert_eq!(tw.min_weight(), Some(-3));
+
+        #[cfg(feature = "random")]
+        {
+            let mut rw: RandWeight<&str> =
+                vec![("a", isize::MAX / 2), ("b", 1)].into_iter().collect();
+            rw.scale_weights(4);
+            assert_eq!(rw.total_weight(), isize::MAX);
+            assert!(rw.contains_with_weight(&"b", 4));
+            rw.scale_weights(0);
+            assert_eq!(rw.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_next_excluding() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+
+        // retry until every backend has been tried
+        let mut tried = Vec::new();
+        while let Some(s) = sw.next_excluding(&tried) {
+            tried.push(s);
+        }
+        assert_eq!(tried, vec!["server1", "server3", "server2"]);
+        assert_eq!(sw.next_excluding(&["server1", "server2", "server3"]), None);
+
+        // the skipped rounds don't distort the long-run shares
+        let picks = sw.next_n(100);
+        assert_eq!(picks.iter().filter(|s| **s == "server1").count(), 50);
+        assert_eq!(picks.iter().filter(|s| **s == "server2").count(), 20);
+
+        let mut rr: RoundrobinWeight<&str> = vec![("a", 1), ("b", 1)].into_iter().collect();
+        assert_eq!(rr.next_excluding(&["a"]), Some("b"));
+        assert_eq!(rr.next_excluding(&["a"]), Some("b"));
+        assert_eq!(rr.next_excluding(&[]), Some("a"));
+    }
+
+    #[test]
+    fn test_next_or() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        assert_eq!(sw.next_or("local"), "local");
+        assert_eq!(sw.next_or_else(|| "local"), "local");
+
+        sw.add("server1", 1);
+        assert_eq!(sw.next_or("local"), "server1");
+        assert_eq!(
+            sw.next_or_else(|| panic!("not called with an item to select")),
+            "server1"
+        );
+
+        let mut rr: Box<dyn Weight<Item = &str>> = Box::new(RoundrobinWeight::new());
+        assert_eq!(rr.next_or("local"), "local");
+    }
 }
@@ -0,0 +1,206 @@
+use super::Weight;
+use alloc::boxed::Box;
+use core::fmt;
+
+/// ObservedWeight wraps any weighted balancer and calls a hook with every item it selects, so
+/// metrics or tracing can be recorded in one place instead of at every call site.
+///
+/// The hook is a boxed closure, so each selection pays for one dynamic call on top of the wrapped
+/// balancer's own work, plus whatever the hook itself does. It runs inside `next()`, so a slow hook
+/// slows every selection down; keep it to cheap work such as bumping a counter. Without a hook
+/// installed a selection only costs an extra branch. The hook must be `Send` so the wrapper can
+/// still be moved to, or shared through a `SharedWeight` with, another thread.
+pub struct ObservedWeight<W: Weight> {
+    inner: W,
+    hook: Option<Box<dyn FnMut(&W::Item) + Send>>,
+}
+
+impl<W: Weight> ObservedWeight<W> {
+    /// wraps `inner` without a hook.
+    pub fn new(inner: W) -> Self {
+        ObservedWeight { inner, hook: None }
+    }
+
+    /// calls `hook` with every item selected from now on, replacing any previous hook.
+    pub fn on_select<F>(&mut self, hook: F)
+    where
+        F: FnMut(&W::Item) + Send + 'static,
+    {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// removes the hook, returning whether one was installed.
+    pub fn clear_hook(&mut self) -> bool {
+        self.hook.take().is_some()
+    }
+
+    /// returns the wrapped balancer.
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    /// unwraps the observed balancer, returning the wrapped one and dropping the hook.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn observe(&mut self, item: &W::Item) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook(item);
+        }
+    }
+}
+
+impl<W: Weight + fmt::Debug> fmt::Debug for ObservedWeight<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservedWeight")
+            .field("inner", &self.inner)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+impl<W: Weight> Weight for ObservedWeight<W> {
+    fn add(&mut self, item: Self::Item, weight: isize) {
+        self.inner.add(item, weight);
+    }
+
+    fn remove(&mut self, item: &Self::Item) -> Option<(Self::Item, isize)>
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.remove(item)
+    }
+
+    fn update_weight(&mut self, item: &Self::Item, weight: isize) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.update_weight(item, weight)
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.inner.all()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Self::Item, isize)> + '_ {
+        self.inner.iter()
+    }
+
+    fn remove_all(&mut self) {
+        self.inner.remove_all();
+    }
+
+    fn clear_items(&mut self) {
+        self.inner.clear_items();
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.inner.limit()
+    }
+
+    fn total_weight(&self) -> isize {
+        self.inner.total_weight()
+    }
+
+    fn min_weight(&self) -> Option<isize> {
+        self.inner.min_weight()
+    }
+
+    fn max_weight(&self) -> Option<isize> {
+        self.inner.max_weight()
+    }
+
+    fn get(&self, index: usize) -> Option<(&Self::Item, isize)> {
+        self.inner.get(index)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    fn contains(&self, item: &Self::Item) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.contains(item)
+    }
+
+    // the hook only sees the item handed out, not the ones the filter turned down
+    fn next_filtered<F: FnMut(&Self::Item) -> bool>(&mut self, pred: F) -> Option<Self::Item> {
+        let item = self.inner.next_filtered(pred)?;
+        self.observe(&item);
+        Some(item)
+    }
+}
+
+impl<W: Weight> Iterator for ObservedWeight<W> {
+    type Item = W::Item;
+
+    fn next(&mut self) -> Option<W::Item> {
+        let item = self.inner.next()?;
+        self.observe(&item);
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ObservedWeight, SmoothWeight, Weight};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_on_select() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 2);
+        sw.add("server2", 1);
+
+        let mut ow = ObservedWeight::new(sw);
+        ow.next();
+
+        let picked = Arc::new(AtomicUsize::new(0));
+        let counter = picked.clone();
+        ow.on_select(move |item| {
+            if *item == "server1" {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        for _ in 0..30 {
+            ow.next();
+        }
+        assert_eq!(picked.load(Ordering::Relaxed), 20);
+
+        assert!(ow.clear_hook());
+        ow.next();
+        assert_eq!(picked.load(Ordering::Relaxed), 20);
+        assert!(!ow.clear_hook());
+    }
+
+    #[test]
+    fn test_next_filtered_observes_selection() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 1);
+
+        let mut ow = ObservedWeight::new(sw);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        ow.on_select(move |item| {
+            assert_eq!(*item, "server2");
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        assert_eq!(ow.next_excluding(&["server1"]), Some("server2"));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,233 @@
+use super::Weight;
+use rand::prelude::{Rng, ThreadRng};
+
+#[derive(Clone, Debug)]
+struct P2CWeightItem<T> {
+    item: T,
+    weight: isize,
+    load: usize,
+}
+
+/// P2CWeight is a load-aware balancer using the "power of two choices": every `next()` draws two
+/// distinct candidates at random in proportion to their weights and returns the one with the
+/// lower reported load, the first candidate winning ties.
+///
+/// Load is whatever the caller reports, typically the number of in-flight requests: either bump
+/// it with `inflight_inc`/`inflight_dec` around each request or set it with `report_load`.
+/// Selection doesn't change it by itself. Items with a non-positive weight are never selected.
+#[derive(Debug, Default)]
+pub struct P2CWeight<T, R = ThreadRng> {
+    items: Vec<P2CWeightItem<T>>,
+    r: R,
+}
+
+impl<T: Clone> P2CWeight<T> {
+    pub fn new() -> Self {
+        P2CWeight::with_rng(rand::thread_rng())
+    }
+}
+
+impl<T: Clone, R: Rng> P2CWeight<T, R> {
+    /// creates a balancer that draws from `rng`.
+    pub fn with_rng(rng: R) -> Self {
+        P2CWeight {
+            items: Vec::new(),
+            r: rng,
+        }
+    }
+
+    fn find_mut(&mut self, item: &T) -> Option<&mut P2CWeightItem<T>>
+    where
+        T: PartialEq,
+    {
+        self.items.iter_mut().find(|w| w.item == *item)
+    }
+
+    /// records one more in-flight request on `item`, returning false if it isn't present.
+    pub fn inflight_inc(&mut self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.find_mut(item).map(|w| w.load += 1).is_some()
+    }
+
+    /// records a finished request on `item`, returning false if it isn't present. The load never
+    /// drops below 0.
+    pub fn inflight_dec(&mut self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.find_mut(item)
+            .map(|w| w.load = w.load.saturating_sub(1))
+            .is_some()
+    }
+
+    /// sets the load of `item`, returning false if it isn't present.
+    pub fn report_load(&mut self, item: &T, load: usize) -> bool
+    where
+        T: PartialEq,
+    {
+        self.find_mut(item).map(|w| w.load = load).is_some()
+    }
+
+    /// returns the current load of `item`, or `None` if it isn't present.
+    pub fn load(&self, item: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        self.items.iter().find(|w| w.item == *item).map(|w| w.load)
+    }
+
+    // draws an index in proportion to the positive weights, leaving out `skip`
+    fn draw(&mut self, total: i128, skip: Option<usize>) -> usize {
+        let mut index = self.r.gen_range(0..total);
+        let mut chosen = 0;
+        for (i, w) in self.items.iter().enumerate() {
+            if Some(i) == skip || w.weight <= 0 {
+                continue;
+            }
+            chosen = i;
+            if index < w.weight as i128 {
+                break;
+            }
+            index -= w.weight as i128;
+        }
+        chosen
+    }
+}
+
+impl<T: Clone, R: Rng> Weight for P2CWeight<T, R> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(P2CWeightItem {
+            item,
+            weight,
+            load: 0,
+        });
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        self.find_mut(item).map(|w| w.weight = weight).is_some()
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+    }
+
+    /// zeroes every reported load.
+    fn reset(&mut self) {
+        for w in &mut self.items {
+            w.load = 0;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone, R: Rng> Iterator for P2CWeight<T, R> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let total: i128 = self.items.iter().map(|w| w.weight.max(0) as i128).sum();
+        if total <= 0 {
+            return None;
+        }
+
+        let first = self.draw(total, None);
+        let rest = total - self.items[first].weight as i128;
+        if rest <= 0 {
+            return Some(self.items[first].item.clone());
+        }
+
+        let second = self.draw(rest, Some(first));
+        let chosen = if self.items[second].load < self.items[first].load {
+            second
+        } else {
+            first
+        };
+        Some(self.items[chosen].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{P2CWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_p2c_weight() {
+        let mut pw: P2CWeight<&str> = P2CWeight::new();
+        pw.add("server1", 1);
+        pw.add("server2", 1);
+        pw.add("server3", 0);
+
+        // with two candidates the less loaded one always wins
+        pw.report_load(&"server1", 10);
+        for _ in 0..100 {
+            assert_eq!(pw.next(), Some("server2"));
+        }
+
+        pw.add("server4", 1);
+        pw.add("server5", 1);
+        for _ in 0..3 {
+            assert!(pw.inflight_inc(&"server4"));
+        }
+        assert!(pw.inflight_dec(&"server4"));
+        assert_eq!(pw.load(&"server4"), Some(2));
+
+        // server1 has the highest load, so it loses every pairing
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..4000 {
+            *results.entry(pw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server1"), None);
+        assert_eq!(results.get("server3"), None);
+        assert!(results["server2"] > results["server4"]);
+
+        pw.reset();
+        assert_eq!(pw.load(&"server1"), Some(0));
+        pw.remove(&"server2");
+        pw.remove(&"server4");
+        pw.remove(&"server5");
+        assert_eq!(pw.next(), Some("server1"));
+    }
+}
@@ -0,0 +1,239 @@
+use super::{SmoothWeight, Weight};
+use alloc::{vec, vec::Vec};
+
+// the horizon of a default balancer, i.e. shares rounded to whole percents
+const DEFAULT_HORIZON: usize = 100;
+
+#[derive(Clone, Debug)]
+struct PlannedWeightItem<T> {
+    item: T,
+    weight: isize,
+}
+
+/// PlannedWeight guarantees exact proportions over a fixed horizon instead of only
+/// asymptotically.
+///
+/// For a horizon `H` it splits `H` picks among the items with the largest-remainder method, so
+/// each item gets its ideal share rounded as fairly as integers allow, then lays the picks out
+/// with the smooth algorithm to interleave them. `next()` walks that schedule and plans a new one
+/// once `H` picks have been handed out or the items change. The default horizon is 100 picks.
+#[derive(Debug)]
+pub struct PlannedWeight<T> {
+    items: Vec<PlannedWeightItem<T>>,
+    horizon: usize,
+    schedule: Vec<usize>,
+    pos: usize,
+}
+
+impl<T> Default for PlannedWeight<T> {
+    fn default() -> Self {
+        PlannedWeight::new(DEFAULT_HORIZON)
+    }
+}
+
+impl<T> PlannedWeight<T> {
+    /// creates a balancer that plans `horizon` picks at a time. A horizon of 0 counts as 1, as an
+    /// empty schedule could never hand anything out.
+    pub const fn new(horizon: usize) -> Self {
+        PlannedWeight {
+            items: Vec::new(),
+            horizon: if horizon == 0 { 1 } else { horizon },
+            schedule: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<T: Clone> PlannedWeight<T> {
+    /// returns how many picks each schedule contains.
+    pub fn horizon(&self) -> usize {
+        self.horizon
+    }
+
+    /// returns how many picks each item gets per horizon, in insertion order.
+    fn quotas(&self) -> Vec<usize> {
+        let total: i128 = self.items.iter().map(|w| w.weight.max(0) as i128).sum();
+        if total == 0 {
+            return vec![0; self.items.len()];
+        }
+
+        let horizon = self.horizon as i128;
+        let mut quotas = Vec::with_capacity(self.items.len());
+        let mut remainders = Vec::with_capacity(self.items.len());
+        for (i, w) in self.items.iter().enumerate() {
+            let share = horizon * w.weight.max(0) as i128;
+            quotas.push((share / total) as usize);
+            remainders.push((share % total, i));
+        }
+
+        let assigned: usize = quotas.iter().sum();
+        remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        for &(_, i) in remainders.iter().take(self.horizon - assigned) {
+            quotas[i] += 1;
+        }
+
+        quotas
+    }
+
+    fn plan(&mut self) {
+        let mut sw: SmoothWeight<usize> = SmoothWeight::new();
+        for (i, quota) in self.quotas().into_iter().enumerate() {
+            if quota > 0 {
+                sw.add(i, quota as isize);
+            }
+        }
+
+        self.schedule.clear();
+        self.schedule.extend(sw.take(self.horizon));
+        self.pos = 0;
+    }
+}
+
+impl<T: Clone> Weight for PlannedWeight<T> {
+    fn add(&mut self, item: T, weight: isize) {
+        self.items.push(PlannedWeightItem { item, weight });
+        self.schedule.clear();
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        self.schedule.clear();
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                w.weight = weight;
+                self.schedule.clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+        self.schedule.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+        self.schedule.clear();
+        self.pos = 0;
+    }
+
+    fn reset(&mut self) {
+        self.schedule.clear();
+        self.pos = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> Iterator for PlannedWeight<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.pos >= self.schedule.len() {
+            self.plan();
+        }
+
+        let index = *self.schedule.get(self.pos)?;
+        self.pos += 1;
+        Some(self.items[index].item.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PlannedWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_planned_weight() {
+        let mut pw: PlannedWeight<&str> = PlannedWeight::new(7);
+        pw.add("server1", 5);
+        pw.add("server2", 2);
+        pw.add("server3", 3);
+
+        for _ in 0..3 {
+            let mut results: HashMap<&str, usize> = HashMap::new();
+            for _ in 0..7 {
+                let s = pw.next().unwrap();
+                *results.entry(s).or_insert(0) += 1;
+            }
+
+            // 3.5, 1.4 and 2.1 picks rounded by largest remainder
+            assert_eq!(results["server1"], 4);
+            assert_eq!(results["server2"], 1);
+            assert_eq!(results["server3"], 2);
+        }
+
+        pw.remove_all();
+        assert_eq!(pw.next(), None);
+    }
+
+    #[test]
+    fn test_horizon() {
+        let mut pw: PlannedWeight<&str> = PlannedWeight::new(0);
+        assert_eq!(pw.horizon(), 1);
+        pw.add("server1", 1);
+        assert_eq!(pw.next_n(3), vec!["server1"; 3]);
+        assert_eq!(PlannedWeight::<&str>::default().horizon(), 100);
+    }
+
+    #[test]
+    fn test_replan() {
+        let mut pw: PlannedWeight<&str> = PlannedWeight::new(4);
+        pw.add("server1", 1);
+        pw.add("server2", 1);
+        assert_eq!(pw.next_n(2), vec!["server1", "server2"]);
+
+        // a change that bypasses update_weight only shows up in the schedule after H picks
+        pw.items[1].weight = 0;
+        assert_eq!(pw.next_n(2), vec!["server1", "server2"]);
+        assert_eq!(pw.next_n(4), vec!["server1"; 4]);
+
+        // update_weight and remove plan afresh right away
+        pw.update_weight(&"server2", 3);
+        assert_eq!(
+            pw.next_n(4),
+            vec!["server2", "server1", "server2", "server2"]
+        );
+        pw.next();
+        pw.remove(&"server1");
+        assert_eq!(pw.next_n(4), vec!["server2"; 4]);
+    }
+}
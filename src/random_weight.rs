@@ -1,36 +1,297 @@
-use super::Weight;
-use rand::prelude::{Rng, ThreadRng};
+use super::{display_summary, same_items, RoundrobinWeight, SmoothWeight, Weight};
+use rand::distributions::{WeightedError, WeightedIndex};
+use rand::prelude::{Rng, SliceRandom, ThreadRng};
+use std::fmt;
+use std::iter::FromIterator;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct RandWeightItem<T> {
     item: T,
     weight: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    count: usize,
 }
 
-// Use the random algorithm to select next item.
-#[derive(Default)]
-pub struct RandWeight<T> {
+/// Use the random algorithm to select next item.
+///
+/// The sum of weights is kept as an `i128` so that even weights close to `isize::MAX` can't
+/// overflow it. Negative weights count as 0, so a bad weight can't drag the sum below the weights
+/// that can actually be drawn.
+///
+/// The random number generator defaults to `ThreadRng`; any other `Rng`, e.g. a seeded `StdRng`,
+/// can be supplied with `with_rng` to get reproducible selections. `ThreadRng` is tied to its
+/// thread, so use a `Send` generator such as `StdRng` to build a balancer on one thread and move it
+/// to another. The balancer is `Clone` whenever the generator is.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RandWeight<T, R = ThreadRng> {
     items: Vec<RandWeightItem<T>>,
-    sum_of_weights: isize,
-    r: ThreadRng,
+    sum_of_weights: i128,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    r: R,
+    #[cfg_attr(feature = "serde", serde(default))]
+    stats: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    floor: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    limit: Option<usize>,
 }
 
 impl<T: Clone> RandWeight<T> {
     pub fn new() -> Self {
+        RandWeight::with_rng(rand::thread_rng())
+    }
+
+    /// creates an empty balancer with room for `capacity` items before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut rw = Self::new();
+        rw.items.reserve_exact(capacity);
+        rw
+    }
+
+    /// creates an empty balancer that holds at most `max` items. Adding more is refused, while
+    /// the weights of items already present can still be updated.
+    pub fn with_limit(max: usize) -> Self {
+        let mut rw = Self::new();
+        rw.limit = Some(max);
+        rw
+    }
+}
+
+impl<T: Clone, R: Rng> RandWeight<T, R> {
+    /// creates a balancer that draws from `rng`.
+    pub fn with_rng(rng: R) -> Self {
         RandWeight {
             items: Vec::new(),
             sum_of_weights: 0,
-            r: rand::thread_rng(),
+            r: rng,
+            stats: false,
+            floor: 0,
+            limit: None,
+        }
+    }
+
+    /// treats every positive weight as at least `floor` when selecting, so light items keep a
+    /// minimum share next to heavy ones. The configured weights are left untouched and a floor of
+    /// 0 turns this off again.
+    pub fn set_selection_floor(&mut self, floor: isize) {
+        self.floor = floor;
+    }
+
+    fn selection_weight(&self, weight: isize) -> isize {
+        floored_weight(weight, self.floor)
+    }
+
+    /// enables counting how many times each item is selected.
+    pub fn enable_stats(&mut self) {
+        self.stats = true;
+    }
+
+    /// returns how many times `item` has been selected while counting was enabled, since the last
+    /// `reset()` or `reset_counters()`.
+    pub fn selection_count(&self, item: &T) -> usize
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .map_or(0, |w| w.count)
+    }
+
+    /// replaces `old` with `new` in place, keeping its weight and rotation state. Returns false if
+    /// `old` isn't present.
+    pub fn replace_item(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *old) {
+            Some(w) => {
+                w.item = new;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// zeroes the selection counters without touching the random state.
+    pub fn reset_counters(&mut self) {
+        for w in &mut self.items {
+            w.count = 0;
+        }
+    }
+
+    fn select(&mut self, index: usize) -> T {
+        if self.stats {
+            self.items[index].count += 1;
+        }
+        self.items[index].item.clone()
+    }
+
+    /// draws up to `n` distinct items without replacement, renormalizing the remaining weights
+    /// after every draw so each pick follows the exact conditional distribution.
+    ///
+    /// Items with a non-positive weight are never drawn. Each draw scans the remaining items, so
+    /// the cost is O(n·m) for `m` items.
+    pub fn sample_without_replacement<G: Rng>(&self, n: usize, rng: &mut G) -> Vec<T> {
+        sample_items(&self.items, n, rng)
+    }
+
+    /// returns every item exactly once, in an order biased toward heavier items: each position is
+    /// drawn without replacement from the items not placed yet. Items with a non-positive weight
+    /// can't be drawn, so they come last in uniformly random order.
+    pub fn weighted_shuffle(&mut self) -> Vec<T> {
+        let mut order = sample_items(&self.items, self.items.len(), &mut self.r);
+        let mut rest: Vec<T> = self
+            .items
+            .iter()
+            .filter(|w| w.weight <= 0)
+            .map(|w| w.item.clone())
+            .collect();
+        rest.shuffle(&mut self.r);
+        order.append(&mut rest);
+        order
+    }
+
+    /// builds a `rand` `WeightedIndex` over the stored weights, in insertion order, so the same
+    /// distribution can be sampled with `rand`'s own machinery. It fails like `WeightedIndex::new`
+    /// does when there are no items, a weight is negative or every weight is 0.
+    pub fn to_weighted_index(&self) -> Result<WeightedIndex<isize>, WeightedError> {
+        WeightedIndex::new(self.items.iter().map(|w| w.weight))
+    }
+
+    /// draws up to `k` distinct items with the balancer's own generator, like
+    /// `sample_without_replacement`. Asking for more items than there are returns every item with
+    /// a positive weight.
+    pub fn sample_k(&mut self, k: usize) -> Vec<T> {
+        sample_items(&self.items, k, &mut self.r)
+    }
+
+    /// selects the next item like `next()`, but draws from `rng` instead of the balancer's own
+    /// generator, which is left untouched. One seeded generator can drive several balancers this
+    /// way and reproduce the same selections run after run.
+    pub fn next_with<G: Rng + ?Sized>(&mut self, rng: &mut G) -> Option<T> {
+        let index = draw_index(&self.items, self.floor, self.sum_of_weights, rng)?;
+        Some(self.select(index))
+    }
+}
+
+fn floored_weight(weight: isize, floor: isize) -> isize {
+    if weight > 0 {
+        weight.max(floor)
+    } else {
+        weight
+    }
+}
+
+fn draw_index<T, G: Rng + ?Sized>(
+    items: &[RandWeightItem<T>],
+    floor: isize,
+    sum_of_weights: i128,
+    rng: &mut G,
+) -> Option<usize> {
+    let total = if floor > 0 {
+        items
+            .iter()
+            .map(|item| floored_weight(item.weight, floor).max(0) as i128)
+            .sum()
+    } else {
+        sum_of_weights
+    };
+
+    // nothing can be drawn from an empty range, e.g. while every weight is 0
+    if total <= 0 {
+        return None;
+    }
+    if items.len() == 1 {
+        return Some(0);
+    }
+
+    let mut index = rng.gen_range(0..total);
+    for (i, item) in items.iter().enumerate() {
+        let weight = floored_weight(item.weight, floor).max(0) as i128;
+        if index < weight {
+            return Some(i);
         }
+        index -= weight;
+    }
+
+    Some(items.len() - 1)
+}
+
+fn sample_items<T: Clone, G: Rng>(items: &[RandWeightItem<T>], n: usize, rng: &mut G) -> Vec<T> {
+    let mut remaining: Vec<&RandWeightItem<T>> =
+        items.iter().filter(|item| item.weight > 0).collect();
+    let mut total: i128 = remaining.iter().map(|item| item.weight as i128).sum();
+    let mut result = Vec::with_capacity(n.min(remaining.len()));
+
+    while result.len() < n && total > 0 {
+        let mut index = rng.gen_range(0..total);
+        let mut chosen = remaining.len() - 1;
+        for (i, item) in remaining.iter().enumerate() {
+            if index < item.weight as i128 {
+                chosen = i;
+                break;
+            }
+            index -= item.weight as i128;
+        }
+
+        let item = remaining.remove(chosen);
+        total -= item.weight as i128;
+        result.push(item.item.clone());
+    }
+
+    result
+}
+
+impl<T, R> RandWeight<T, R> {
+    /// consumes the balancer and yields its items with their weights in insertion order, moving
+    /// them out rather than cloning them. Only the items come out: the generator and the
+    /// selection counters are dropped.
+    pub fn into_items(self) -> impl ExactSizeIterator<Item = (T, isize)> {
+        self.items.into_iter().map(|w| (w.item, w.weight))
     }
 }
 
-impl<T: Clone> Weight for RandWeight<T> {
+impl<T: Clone, R: Rng> Weight for RandWeight<T, R> {
     fn add(&mut self, item: T, weight: isize) {
-        let weight_item = RandWeightItem { item, weight };
+        if self.limit.is_some_and(|max| self.items.len() >= max) {
+            return;
+        }
+
+        let weight_item = RandWeightItem {
+            item,
+            weight,
+            count: 0,
+        };
 
         self.items.push(weight_item);
-        self.sum_of_weights += weight;
+        self.sum_of_weights += weight.max(0) as i128;
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.swap_remove(index);
+        self.sum_of_weights -= removed.weight.max(0) as i128;
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => {
+                self.sum_of_weights += weight.max(0) as i128 - w.weight.max(0) as i128;
+                w.weight = weight;
+                true
+            }
+            None => false,
+        }
     }
 
     fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
@@ -39,39 +300,167 @@ impl<T: Clone> Weight for RandWeight<T> {
             .map(|item| (item.item.clone(), item.weight))
     }
 
+    fn scale_weights(&mut self, factor: isize) {
+        for w in &mut self.items {
+            w.weight = w.weight.saturating_mul(factor);
+        }
+        self.sum_of_weights = self.items.iter().map(|w| w.weight.max(0) as i128).sum();
+    }
+
+    fn retain<F: FnMut(&T, isize) -> bool>(&mut self, mut f: F) {
+        self.items.retain(|w| f(&w.item, w.weight));
+        self.sum_of_weights = self.items.iter().map(|w| w.weight.max(0) as i128).sum();
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (T, isize)> + '_ {
+        let items = std::mem::take(&mut self.items);
+        self.remove_all();
+        items.into_iter().map(|w| (w.item, w.weight))
+    }
+
+    fn next_filtered<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Option<T> {
+        let eligible: Vec<(usize, i128)> = (0..self.items.len())
+            .filter(|&i| pred(&self.items[i].item))
+            .map(|i| {
+                (
+                    i,
+                    self.selection_weight(self.items[i].weight).max(0) as i128,
+                )
+            })
+            .collect();
+        let total: i128 = eligible.iter().map(|(_, w)| w).sum();
+        if total <= 0 {
+            return None;
+        }
+
+        let mut index = self.r.gen_range(0..total);
+        for (i, w) in eligible {
+            if index < w {
+                return Some(self.select(i));
+            }
+            index -= w;
+        }
+
+        None
+    }
+
+    fn replace(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.replace_item(old, new)
+    }
+
     fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
         self.items.clear();
-        self.r = rand::thread_rng();
+        self.sum_of_weights = 0;
     }
 
+    // random selection keeps no state between picks apart from the counters; the rng is left
+    // alone so a seeded sequence isn't disturbed.
     fn reset(&mut self) {
-        self.r = rand::thread_rng();
+        self.reset_counters();
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn total_weight(&self) -> isize {
+        self.sum_of_weights.min(isize::MAX as i128) as isize
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> FromIterator<(T, isize)> for RandWeight<T> {
+    fn from_iter<I: IntoIterator<Item = (T, isize)>>(iter: I) -> Self {
+        let mut w = RandWeight::new();
+        w.extend(iter);
+        w
+    }
+}
+
+impl<T: Clone, R: Rng> Extend<(T, isize)> for RandWeight<T, R> {
+    fn extend<I: IntoIterator<Item = (T, isize)>>(&mut self, iter: I) {
+        for (item, weight) in iter {
+            self.add(item, weight);
+        }
+    }
+}
+
+/// Keeps the items with their weights and draws from a fresh `ThreadRng`. The long-run shares
+/// carry over, the smooth interleaving doesn't.
+impl<T: Clone> From<SmoothWeight<T>> for RandWeight<T> {
+    fn from(w: SmoothWeight<T>) -> Self {
+        w.into_items().collect()
+    }
+}
+
+/// Keeps the items in insertion order with their weights, drawing them at random from a fresh
+/// `ThreadRng` instead of rotating through them.
+impl<T: Clone> From<RoundrobinWeight<T>> for RandWeight<T> {
+    fn from(w: RoundrobinWeight<T>) -> Self {
+        w.into_items().collect()
     }
 }
 
-impl<T: Clone> Iterator for RandWeight<T> {
+impl<T: Clone, R: Rng> Iterator for RandWeight<T, R> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        if self.items.len() <= 1 {
-            return self.items.first().map(|item| item.item.clone());
-        }
+        let index = draw_index(&self.items, self.floor, self.sum_of_weights, &mut self.r)?;
+        Some(self.select(index))
+    }
+}
 
-        let mut index = self.r.gen_range(0..self.sum_of_weights);
-        for item in &self.items {
-            index -= item.weight;
-            if index <= 0 {
-                return Some(item.item.clone());
-            }
-        }
+/// Two balancers are equal when they hold the same `(item, weight)` pairs, in any order. The
+/// random number generator, the counters and the settings are not compared.
+impl<T: PartialEq, R> PartialEq for RandWeight<T, R> {
+    fn eq(&self, other: &Self) -> bool {
+        same_items(
+            self.items.iter().map(|w| (&w.item, w.weight)),
+            other.items.iter().map(|w| (&w.item, w.weight)),
+        )
+    }
+}
 
-        self.items.last().map(|item| item.item.clone())
+impl<T: fmt::Display, R> fmt::Display for RandWeight<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_summary(
+            f,
+            "RandWeight",
+            self.items.iter().map(|w| (&w.item, w.weight)),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{RandWeight, Weight};
+    use rand::{rngs::StdRng, SeedableRng};
     use std::collections::HashMap;
 
     #[test]
@@ -94,4 +483,381 @@ mod tests {
         // assert!(results["server2"] > 1000 && results["server1"] < 3000);
         // assert!(results["server3"] > 2000 && results["server1"] < 4000);
     }
+
+    #[test]
+    fn test_sample_without_replacement() {
+        let weights = [("server1", 5), ("server2", 2), ("server3", 3)];
+        let mut sw: RandWeight<&str> = RandWeight::new();
+        for (item, weight) in weights.iter() {
+            sw.add(*item, *weight);
+        }
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let rounds = 100_000;
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..rounds {
+            let picked = sw.sample_without_replacement(2, &mut rng);
+            assert_eq!(picked.len(), 2);
+            assert_ne!(picked[0], picked[1]);
+            for s in picked {
+                *results.entry(s).or_insert(0) += 1;
+            }
+        }
+
+        // P(i in sample) = w_i/W + sum_{j != i} w_j/W * w_i/(W - w_j)
+        let total: f64 = weights.iter().map(|(_, w)| *w as f64).sum();
+        for (item, weight) in weights.iter() {
+            let w = *weight as f64;
+            let mut expected = w / total;
+            for (other, other_weight) in weights.iter() {
+                if other != item {
+                    let ow = *other_weight as f64;
+                    expected += ow / total * w / (total - ow);
+                }
+            }
+
+            let actual = results[item] as f64 / rounds as f64;
+            assert!(
+                (actual - expected).abs() < 0.01,
+                "{}: {} vs {}",
+                item,
+                actual,
+                expected
+            );
+        }
+
+        assert_eq!(sw.sample_without_replacement(5, &mut rng).len(), 3);
+    }
+
+    #[test]
+    fn test_reset_counters() {
+        let mut sw: RandWeight<&str> = RandWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.enable_stats();
+
+        for _ in 0..100 {
+            sw.next();
+        }
+        assert_eq!(
+            sw.selection_count(&"server1") + sw.selection_count(&"server2"),
+            100
+        );
+
+        sw.reset_counters();
+        assert_eq!(sw.selection_count(&"server1"), 0);
+        assert_eq!(sw.selection_count(&"server2"), 0);
+    }
+
+    #[test]
+    fn test_selection_floor() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", 1000);
+        rw.add("server2", 1);
+        rw.set_selection_floor(250);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..10000 {
+            *results.entry(rw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!(results["server2"] > 1500 && results["server2"] < 2500);
+
+        rw.set_selection_floor(0);
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..10000 {
+            *results.entry(rw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!(results.get("server2").copied().unwrap_or(0) < 100);
+    }
+
+    #[test]
+    fn test_extreme_weights() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", isize::MAX);
+        rw.add("server2", isize::MAX);
+        rw.add("server3", 1);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..1000 {
+            *results.entry(rw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!(results["server1"] > 400 && results["server2"] > 400);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(rw.sample_without_replacement(3, &mut rng).len(), 3);
+    }
+
+    #[test]
+    fn test_large_weight_sum() {
+        // thousands of large weights summing well past isize::MAX / 2 must not wrap around
+        let weight = isize::MAX / 1000;
+        let mut rw = RandWeight::with_rng(StdRng::seed_from_u64(3));
+        for i in 0..4000 {
+            rw.add(i, weight);
+        }
+        assert_eq!(rw.sum_of_weights, 4000 * weight as i128);
+        assert_eq!(rw.total_weight(), isize::MAX);
+
+        let mut results: HashMap<i32, usize> = HashMap::new();
+        for _ in 0..40_000 {
+            *results.entry(rw.next().unwrap() / 1000).or_insert(0) += 1;
+        }
+        for quarter in 0..4 {
+            assert!(results[&quarter] > 9000 && results[&quarter] < 11000);
+        }
+
+        // removing items takes their weight back off exactly
+        for i in 0..3999 {
+            rw.remove(&i);
+        }
+        assert_eq!(rw.sum_of_weights, weight as i128);
+        assert_eq!(rw.next(), Some(3999));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", 5);
+        rw.add("server2", 2);
+        rw.add("server3", 3);
+
+        assert_eq!(rw.remove(&"server4"), None);
+        assert_eq!(rw.sum_of_weights, 10);
+
+        assert_eq!(rw.remove(&"server1"), Some(("server1", 5)));
+        assert_eq!(rw.sum_of_weights, 5);
+        for _ in 0..1000 {
+            assert_ne!(rw.next(), Some("server1"));
+        }
+    }
+
+    #[test]
+    fn test_update_weight() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", 5);
+        rw.add("server2", 2);
+
+        assert!(!rw.update_weight(&"server3", 1));
+        assert!(rw.update_weight(&"server1", 1));
+        assert_eq!(rw.sum_of_weights, 3);
+    }
+
+    #[test]
+    fn test_with_rng() {
+        let mut a = RandWeight::with_rng(StdRng::seed_from_u64(42));
+        let mut b = RandWeight::with_rng(StdRng::seed_from_u64(42));
+        for w in [&mut a, &mut b].iter_mut() {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+        }
+
+        let picks: Vec<&str> = a.by_ref().take(100).collect();
+        assert_eq!(picks, b.by_ref().take(100).collect::<Vec<_>>());
+
+        // resetting doesn't reseed, so both keep producing the same sequence
+        a.reset();
+        b.reset();
+        assert_eq!(
+            a.take(100).collect::<Vec<_>>(),
+            b.take(100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_next_with() {
+        let mut a: RandWeight<&str> = RandWeight::new();
+        let mut b: RandWeight<&str> = RandWeight::new();
+        for w in [&mut a, &mut b].iter_mut() {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+        }
+
+        // one shared generator drives both balancers
+        let mut rng = StdRng::seed_from_u64(7);
+        let picks: Vec<(&str, &str)> = (0..100)
+            .map(|_| {
+                (
+                    a.next_with(&mut rng).unwrap(),
+                    b.next_with(&mut rng).unwrap(),
+                )
+            })
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for (x, y) in picks {
+            assert_eq!(a.next_with(&mut rng), Some(x));
+            assert_eq!(b.next_with(&mut rng), Some(y));
+        }
+
+        let mut empty: RandWeight<&str> = RandWeight::new();
+        assert_eq!(empty.next_with(&mut rng), None);
+    }
+
+    #[test]
+    fn test_zero_total_weight() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        assert_eq!(rw.next(), None);
+
+        rw.add("server1", 0);
+        assert_eq!(rw.next(), None);
+        rw.add("server2", 0);
+        assert_eq!(rw.next(), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut rw: RandWeight<String> = RandWeight::new();
+        rw.add("server1".to_string(), 5);
+        rw.add("server2".to_string(), 2);
+
+        let json = serde_json::to_string(&rw).unwrap();
+        let mut restored: RandWeight<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored.all().collect::<Vec<_>>(),
+            rw.all().collect::<Vec<_>>()
+        );
+        assert_eq!(restored.sum_of_weights, 7);
+        assert!(restored.next().is_some());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_older_formats() {
+        // fields added since are filled in with their defaults
+        let jsons = [
+            r#"{"items":[{"item":"a","weight":2,"count":0}],"sum_of_weights":2,
+                "stats":false,"floor":0}"#,
+            r#"{"items":[{"item":"a","weight":2}],"sum_of_weights":2}"#,
+        ];
+        for json in jsons.iter() {
+            let mut rw: RandWeight<String> = serde_json::from_str(json).unwrap();
+            assert_eq!(rw.limit(), None);
+            assert_eq!(rw.next().as_deref(), Some("a"));
+        }
+    }
+
+    #[test]
+    fn test_sample_k() {
+        let mut rw: RandWeight<usize> = RandWeight::new();
+        for i in 0..20 {
+            rw.add(i, (i % 5) as isize);
+        }
+
+        for _ in 0..1000 {
+            let mut picked = rw.sample_k(3);
+            assert_eq!(picked.len(), 3);
+            assert!(picked.iter().all(|i| i % 5 != 0));
+            picked.sort();
+            picked.dedup();
+            assert_eq!(picked.len(), 3);
+        }
+
+        let mut all = rw.sample_k(100);
+        all.sort();
+        assert_eq!(all, (0..20).filter(|i| i % 5 != 0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_next_filtered() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", 5);
+        rw.add("server2", 2);
+        rw.add("server3", 3);
+        rw.add("server4", 0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..5000 {
+            let s = rw.next_filtered(|s| *s != "server1").unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server1"), None);
+        assert_eq!(results.get("server4"), None);
+        assert!((1700..2300).contains(&results["server2"]));
+
+        assert_eq!(rw.next_filtered(|s| *s == "server4"), None);
+    }
+
+    #[test]
+    fn test_to_weighted_index() {
+        use rand::distributions::{Distribution, WeightedError};
+
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        assert_eq!(rw.to_weighted_index().unwrap_err(), WeightedError::NoItem);
+
+        rw.add("server1", 0);
+        assert_eq!(
+            rw.to_weighted_index().unwrap_err(),
+            WeightedError::AllWeightsZero
+        );
+
+        rw.add("server2", 3);
+        rw.add("server3", 1);
+        let index = rw.to_weighted_index().unwrap();
+        let items: Vec<&str> = rw.all().map(|(item, _)| item).collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..4000 {
+            *results.entry(items[index.sample(&mut rng)]).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server1"), None);
+        assert!((2700..3300).contains(&results["server2"]));
+
+        rw.add("server4", -1);
+        assert_eq!(
+            rw.to_weighted_index().unwrap_err(),
+            WeightedError::InvalidWeight
+        );
+    }
+
+    #[test]
+    fn test_weighted_shuffle() {
+        let mut rw: RandWeight<usize> = RandWeight::new();
+        for i in 0..5 {
+            rw.add(i, 1 << i);
+        }
+        rw.add(5, 0);
+
+        let rounds = 2000;
+        let mut positions = [0usize; 6];
+        for _ in 0..rounds {
+            let order = rw.weighted_shuffle();
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, vec![0, 1, 2, 3, 4, 5]);
+            assert_eq!(order[5], 5);
+
+            for (pos, item) in order.into_iter().enumerate() {
+                positions[item] += pos;
+            }
+        }
+
+        // heavier items land earlier on average
+        for i in 1..5 {
+            assert!(positions[i] < positions[i - 1]);
+        }
+    }
+
+    #[test]
+    fn test_negative_weight() {
+        let mut rw: RandWeight<&str> = RandWeight::new();
+        rw.add("server1", -1);
+        assert_eq!(rw.sum_of_weights, 0);
+        assert_eq!(rw.next(), None);
+
+        rw.add("server2", 2);
+        rw.add("server3", 1);
+        assert_eq!(rw.sum_of_weights, 3);
+        for _ in 0..1000 {
+            assert_ne!(rw.next(), Some("server1"));
+        }
+
+        rw.update_weight(&"server2", -5);
+        assert_eq!(rw.sum_of_weights, 1);
+        assert_eq!(rw.remove(&"server2"), Some(("server2", -5)));
+        assert_eq!(rw.sum_of_weights, 1);
+        assert_eq!(rw.next(), Some("server3"));
+    }
 }
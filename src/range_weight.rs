@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[derive(Clone, Debug)]
+struct RangeWeightItem<T> {
+    item: T,
+    weight: isize,
+    end: i128,
+}
+
+/// RangeWeight splits the key space `[0, total_weight)` into contiguous ranges, one per item,
+/// whose sizes are the items' weights.
+///
+/// Keys are routed deterministically to the item owning `key mod total_weight`, which gives a
+/// stable key-to-shard mapping for range-sharded data. Unlike consistent hashing, changing one
+/// weight shifts the boundaries of every range after it. The key space is measured in `i128`, so
+/// weights up to `isize::MAX` can't overflow it.
+#[derive(Clone, Debug, Default)]
+pub struct RangeWeight<T> {
+    items: Vec<RangeWeightItem<T>>,
+    total_weight: i128,
+}
+
+impl<T> RangeWeight<T> {
+    pub const fn new() -> Self {
+        RangeWeight {
+            items: Vec::new(),
+            total_weight: 0,
+        }
+    }
+
+    /// appends an item owning the next `weight` keys. Non-positive weights own no keys.
+    pub fn add(&mut self, item: T, weight: isize) {
+        self.total_weight += weight.max(0) as i128;
+        self.items.push(RangeWeightItem {
+            item,
+            weight,
+            end: self.total_weight,
+        });
+    }
+
+    /// returns the size of the key space.
+    pub fn total_weight(&self) -> i128 {
+        self.total_weight
+    }
+
+    /// returns the item owning `key % total_weight`, wrapping negative keys into range.
+    pub fn get(&self, key: isize) -> Option<&T> {
+        if self.total_weight <= 0 {
+            return None;
+        }
+
+        let key = (key as i128).rem_euclid(self.total_weight);
+        let index = self.items.partition_point(|w| w.end <= key);
+        self.items.get(index).map(|w| &w.item)
+    }
+
+    /// returns the keys owned by `item`.
+    pub fn range_of(&self, item: &T) -> Option<Range<i128>>
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .map(|w| w.end - w.weight.max(0) as i128..w.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RangeWeight;
+
+    #[test]
+    fn test_range_weight() {
+        let mut rw: RangeWeight<&str> = RangeWeight::new();
+        rw.add("shard1", 5);
+        rw.add("shard2", 0);
+        rw.add("shard3", 2);
+        rw.add("shard4", 3);
+
+        assert_eq!(rw.total_weight(), 10);
+        assert_eq!(rw.range_of(&"shard3"), Some(5..7));
+        assert_eq!(rw.range_of(&"shard2"), Some(5..5));
+
+        let owners: Vec<&str> = (0..10).map(|key| *rw.get(key).unwrap()).collect();
+        assert_eq!(
+            owners,
+            vec![
+                "shard1", "shard1", "shard1", "shard1", "shard1", "shard3", "shard3", "shard4",
+                "shard4", "shard4"
+            ]
+        );
+        assert_eq!(rw.get(15), Some(&"shard3"));
+        assert_eq!(rw.get(-1), Some(&"shard4"));
+
+        assert_eq!(RangeWeight::<&str>::new().get(3), None);
+    }
+
+    #[test]
+    fn test_large_weights() {
+        let mut rw: RangeWeight<&str> = RangeWeight::new();
+        rw.add("shard1", isize::MAX);
+        rw.add("shard2", isize::MAX);
+
+        let max = isize::MAX as i128;
+        assert_eq!(rw.total_weight(), 2 * max);
+        assert_eq!(rw.range_of(&"shard2"), Some(max..2 * max));
+        assert_eq!(rw.get(isize::MAX - 1), Some(&"shard1"));
+        assert_eq!(rw.get(-1), Some(&"shard2"));
+    }
+}
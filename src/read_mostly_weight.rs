@@ -0,0 +1,129 @@
+use super::{RoundrobinWeight, Weight};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    RwLock,
+};
+
+/// ReadMostlyWeight is a weighted round-robin selector for configurations that change rarely but
+/// are selected from constantly by many threads.
+///
+/// The items live behind a `RwLock` and selection never mutates them: a shared atomic request
+/// counter is mapped to an item with `RoundrobinWeight::roundrobin_pick_for`. Any number of
+/// threads can therefore select concurrently under the read lock, and only configuration changes
+/// take the write lock. `reconfigure` rebuilds the balancer's pick table before releasing the
+/// write lock, so a read costs O(log k + n) for k distinct weights and n items, whatever the size
+/// of the weights.
+///
+/// Consistency model: every `next()` sees one complete configuration, either the one before or
+/// the one after a concurrent change, never a partial one. Use `reconfigure` to apply several
+/// changes as a single step. The request counter keeps running across changes, so a new
+/// configuration picks up mid-cycle rather than from its first item.
+#[derive(Debug, Default)]
+pub struct ReadMostlyWeight<T> {
+    inner: RwLock<RoundrobinWeight<T>>,
+    counter: AtomicU64,
+}
+
+impl<T: Clone> ReadMostlyWeight<T> {
+    pub const fn new() -> Self {
+        ReadMostlyWeight {
+            inner: RwLock::new(RoundrobinWeight::new()),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// adds a weighted item for selection.
+    pub fn add(&self, item: T, weight: isize) {
+        self.reconfigure(|w| w.add(item, weight));
+    }
+
+    /// removes all weighted items.
+    pub fn remove_all(&self) {
+        self.reconfigure(|w| w.remove_all());
+    }
+
+    /// applies `f` to the configuration under the write lock, so readers observe all of its
+    /// changes at once. The cached aggregates are brought up to date before the lock is released,
+    /// so readers never recompute them.
+    pub fn reconfigure<F: FnOnce(&mut RoundrobinWeight<T>)>(&self, f: F) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        f(&mut inner);
+        inner.rebalance();
+    }
+
+    /// selects the next item under the read lock.
+    pub fn next(&self) -> Option<T> {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .roundrobin_pick_for(n)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ReadMostlyWeight, Weight};
+    use std::sync::atomic::Ordering;
+    use std::{collections::HashMap, sync::Arc, thread};
+
+    #[test]
+    fn test_read_mostly_weight() {
+        let rmw: Arc<ReadMostlyWeight<&str>> = Arc::new(ReadMostlyWeight::new());
+        rmw.reconfigure(|w| {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+        });
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let rmw = Arc::clone(&rmw);
+                thread::spawn(move || (0..250).map(|_| rmw.next().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for handle in handles {
+            for s in handle.join().unwrap() {
+                *results.entry(s).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(results["server1"], 500);
+        assert_eq!(results["server2"], 200);
+        assert_eq!(results["server3"], 300);
+
+        rmw.remove_all();
+        assert_eq!(rmw.next(), None);
+    }
+
+    #[test]
+    fn test_reconfigure_rebalances() {
+        let rmw: ReadMostlyWeight<&str> = ReadMostlyWeight::new();
+        rmw.reconfigure(|w| {
+            w.add("server1", 4);
+            w.add("server2", 2);
+            w.update_weight(&"server1", 2);
+        });
+        assert!(!rmw.inner.read().unwrap().needs_rebalance());
+
+        rmw.reconfigure(|w| {
+            w.remove(&"server2");
+        });
+        assert!(!rmw.inner.read().unwrap().needs_rebalance());
+        assert_eq!(rmw.next(), Some("server1"));
+    }
+
+    #[test]
+    fn test_large_weights() {
+        let rmw: ReadMostlyWeight<&str> = ReadMostlyWeight::new();
+        rmw.add("server1", 1_000_000);
+        rmw.add("server2", 1);
+
+        rmw.counter.store(999_999, Ordering::Relaxed);
+        assert_eq!(rmw.next(), Some("server1"));
+        assert_eq!(rmw.next(), Some("server2"));
+        assert_eq!(rmw.next(), Some("server1"));
+    }
+}
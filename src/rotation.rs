@@ -0,0 +1,94 @@
+// bookkeeping shared by the balancers that run the smooth weighted round-robin over their own
+// item storage
+
+// hands the current weight of a removed item to the one in slot `preferred`, or to any other
+// selectable item if that one isn't, so the current weights keep summing to zero. If nothing
+// selectable is left, all current weights start over from 0 instead. `state` gives an item's weight
+// and its current weight, or `None` for an item outside the rotation the removed one was in.
+pub(crate) fn hand_off<I>(
+    items: &mut [I],
+    preferred: usize,
+    amount: i128,
+    mut state: impl FnMut(&mut I) -> Option<(isize, &mut i128)>,
+) {
+    let mut selectable = |w: &mut I| state(w).is_some_and(|(weight, _)| weight > 0);
+    let target = if items.get_mut(preferred).is_some_and(&mut selectable) {
+        Some(preferred)
+    } else {
+        items.iter_mut().position(selectable)
+    };
+    match target {
+        Some(i) => {
+            if let Some((_, current_weight)) = state(&mut items[i]) {
+                *current_weight += amount;
+            }
+        }
+        None => {
+            for w in items {
+                if let Some((_, current_weight)) = state(w) {
+                    *current_weight = 0;
+                }
+            }
+        }
+    }
+}
+
+// spreads `amount` over the items in proportion to their weights, the rounding rest going to the
+// heaviest one, so the current weights keep summing to zero. If no item has a positive weight, all
+// current weights start over from 0 instead. `state` is as for `hand_off`.
+pub(crate) fn redistribute<I>(
+    items: &mut [I],
+    amount: i128,
+    mut state: impl FnMut(&mut I) -> Option<(isize, &mut i128)>,
+) {
+    let total: i128 = items
+        .iter_mut()
+        .filter_map(&mut state)
+        .map(|(weight, _)| weight.max(0) as i128)
+        .sum();
+    if total <= 0 {
+        for w in items {
+            if let Some((_, current_weight)) = state(w) {
+                *current_weight = 0;
+            }
+        }
+        return;
+    }
+
+    let mut rest = amount;
+    let mut heaviest: Option<(usize, isize)> = None;
+    for (i, w) in items.iter_mut().enumerate() {
+        if let Some((weight, current_weight)) = state(w) {
+            let share_weight = weight.max(0) as i128;
+            let share = amount.checked_mul(share_weight).map_or_else(
+                || (amount as f64 * share_weight as f64 / total as f64) as i128,
+                |product| product / total,
+            );
+            *current_weight += share;
+            rest -= share;
+            if heaviest.is_none_or(|(_, max)| weight >= max) {
+                heaviest = Some((i, weight));
+            }
+        }
+    }
+
+    if let Some((i, _)) = heaviest {
+        if let Some((_, current_weight)) = state(&mut items[i]) {
+            *current_weight += rest;
+        }
+    }
+}
+
+// rescales the current weight of an item whose weight goes from `old` to `new`, so it keeps its
+// relative position in the cycle. An item that wasn't or won't be selectable restarts at 0.
+pub(crate) fn rescale(current_weight: i128, old: isize, new: isize) -> i128 {
+    if old <= 0 || new <= 0 {
+        return 0;
+    }
+
+    let (old, new) = (old as i128, new as i128);
+    current_weight.checked_mul(new).map_or_else(
+        || (current_weight as f64 * new as f64 / old as f64) as i128,
+        |product| product / old,
+    )
+}
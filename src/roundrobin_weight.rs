@@ -1,9 +1,27 @@
-use super::Weight;
+#[cfg(feature = "random")]
+use super::RandWeight;
+use super::{display_summary, same_items, SmoothWeight, Weight};
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FromIterator;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct RRWeightItem<T> {
     item: T,
     weight: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    count: usize,
+}
+
+// a run of rounds that select the same items: every item whose weight is at least `level`, in
+// order, `count` of them per round. `end` is the number of selections in the cycle up to and
+// including this run.
+#[derive(Clone, Debug)]
+struct RoundBand {
+    end: u128,
+    level: isize,
+    count: usize,
 }
 
 /// RoundrobinWeight is a struct that contains weighted items implement LVS weighted round robin
@@ -12,13 +30,54 @@ struct RRWeightItem<T> {
 /// http://kb.linuxvirtualitem.org/wiki/Weighted_Round-Robin_Scheduling
 ///
 /// http://zh.linuxvirtualitem.org/node/37
-#[derive(Debug, Default)]
+///
+/// The gcd and the maximum of the weights are cached, along with a table of how many selections
+/// each run of rounds makes for `roundrobin_pick_for`. Changing the items only marks them stale,
+/// and they are recomputed by the next selection or `rebalance()`, so a batch of changes pays for
+/// one rescan.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RoundrobinWeight<T> {
     items: Vec<RRWeightItem<T>>,
     gcd: isize,
     max_w: isize,
     i: isize,
     cw: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    stats: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    floor: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    limit: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    bands: Vec<RoundBand>,
+    // the table isn't serialized, so a deserialized balancer starts out stale
+    #[cfg_attr(feature = "serde", serde(skip, default = "stale"))]
+    dirty: bool,
+}
+
+#[cfg(feature = "serde")]
+fn stale() -> bool {
+    true
+}
+
+// the cursor starts at -1, like `reset()` leaves it, so the first selection scans from the first
+// item
+impl<T> Default for RoundrobinWeight<T> {
+    fn default() -> Self {
+        RoundrobinWeight {
+            items: Vec::new(),
+            gcd: 0,
+            max_w: 0,
+            i: -1,
+            cw: 0,
+            stats: false,
+            floor: 0,
+            limit: None,
+            bands: Vec::new(),
+            dirty: false,
+        }
+    }
 }
 
 impl<T: Clone> RoundrobinWeight<T> {
@@ -27,31 +86,330 @@ impl<T: Clone> RoundrobinWeight<T> {
             items: Vec::new(),
             gcd: 0,
             max_w: 0,
-            i: 0,
+            i: -1,
             cw: 0,
+            stats: false,
+            floor: 0,
+            limit: None,
+            bands: Vec::new(),
+            dirty: false,
         }
     }
-}
 
-impl<T: Clone> Weight for RoundrobinWeight<T> {
-    fn add(&mut self, item: T, weight: isize) {
-        let weight_item = RRWeightItem { item, weight };
+    /// creates an empty balancer with room for `capacity` items before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        RoundrobinWeight {
+            items: Vec::with_capacity(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// creates a balancer holding `items`, in order, ready to select.
+    ///
+    /// `add`, `extend` and `collect` leave the gcd, the maximum weight and the pick table to be
+    /// recomputed by the first selection. This moves the items into a single allocation and
+    /// computes all of them up front in one O(n log n) pass, so large static configurations are
+    /// ready for `next()` and `roundrobin_pick_for` right away.
+    pub fn from_weights(items: Vec<(T, isize)>) -> Self {
+        let mut w = RoundrobinWeight {
+            items: items
+                .into_iter()
+                .map(|(item, weight)| RRWeightItem {
+                    item,
+                    weight,
+                    count: 0,
+                })
+                .collect(),
+            ..Self::new()
+        };
+        w.dirty = true;
+        w.rebalance();
+        w
+    }
+
+    /// creates an empty balancer that holds at most `max` items. Adding more is refused, while
+    /// the weights of items already present can still be updated.
+    pub fn with_limit(max: usize) -> Self {
+        RoundrobinWeight {
+            limit: Some(max),
+            ..Self::new()
+        }
+    }
+
+    /// treats every positive weight as at least `floor` when selecting, so light items keep a
+    /// minimum share next to heavy ones. The configured weights are left untouched and a floor of
+    /// 0 turns this off again.
+    pub fn set_selection_floor(&mut self, floor: isize) {
+        self.floor = floor;
+        self.dirty = true;
+        self.i = -1;
+        self.cw = 0;
+    }
 
+    fn selection_weight(&self, weight: isize) -> isize {
         if weight > 0 {
-            if self.gcd == 0 {
-                self.gcd = weight;
-                self.max_w = weight;
-                self.i = -1;
-                self.cw = 0;
+            weight.max(self.floor)
+        } else {
+            weight
+        }
+    }
+
+    /// returns true if items or weights changed since the cached gcd and maximum weight were last
+    /// computed. The next selection recomputes them.
+    pub fn needs_rebalance(&self) -> bool {
+        self.dirty
+    }
+
+    // gcd and max_w are cached across all items, so every path that removes an item or lowers a
+    // weight must mark them dirty, otherwise a stale max_w keeps the cursor cycling through weights
+    // that no longer exist.
+    fn compute_gcd_max(&self) -> (isize, isize) {
+        let (mut g, mut max_w) = (0, 0);
+        for w in &self.items {
+            let weight = self.selection_weight(w.weight);
+            if weight > 0 {
+                g = if g == 0 { weight } else { gcd(g, weight) };
+                max_w = max_w.max(weight);
+            }
+        }
+        (g, max_w)
+    }
+
+    // the cached gcd and max_w, or fresh ones if they are stale, for callers that can't update
+    // the cache
+    fn gcd_max(&self) -> (isize, isize) {
+        if self.dirty {
+            self.compute_gcd_max()
+        } else {
+            (self.gcd, self.max_w)
+        }
+    }
+
+    // splits the cycle into runs of rounds that select the same items. There is one run per
+    // distinct weight, so a cycle of max_w / gcd rounds takes at most n entries.
+    fn compute_bands(&self, g: isize) -> Vec<RoundBand> {
+        let mut weights: Vec<isize> = self
+            .items
+            .iter()
+            .map(|w| self.selection_weight(w.weight))
+            .filter(|weight| *weight > 0)
+            .collect();
+        weights.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut bands = Vec::new();
+        let mut end = 0u128;
+        let mut start = 0;
+        while start < weights.len() {
+            let level = weights[start];
+            let count = start + weights[start..].iter().take_while(|w| **w == level).count();
+            let next = weights.get(count).copied().unwrap_or(0);
+            end += ((level - next) / g) as u128 * count as u128;
+            bands.push(RoundBand { end, level, count });
+            start = count;
+        }
+        bands
+    }
+
+    /// recomputes the cached gcd, maximum weight and pick table now if they are stale. Selections
+    /// do this on their own, but `roundrobin_pick_for` only borrows the balancer and has to work
+    /// them out afresh on every call until this runs, so call it after changing the items of a
+    /// balancer that is picked from through a shared reference.
+    pub fn rebalance(&mut self) {
+        if self.dirty {
+            let (g, max_w) = self.compute_gcd_max();
+            self.gcd = g;
+            self.max_w = max_w;
+            self.bands = if g > 0 {
+                self.compute_bands(g)
             } else {
-                self.gcd = gcd(self.gcd, weight);
-                if self.max_w < weight {
-                    self.max_w = weight;
+                Vec::new()
+            };
+            self.cw = self.cw.min(max_w);
+            self.dirty = false;
+        }
+    }
+
+    /// enables counting how many times each item is selected.
+    pub fn enable_stats(&mut self) {
+        self.stats = true;
+    }
+
+    /// returns how many times `item` has been selected while counting was enabled, since the last
+    /// `reset()` or `reset_counters()`.
+    pub fn selection_count(&self, item: &T) -> usize
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .map_or(0, |w| w.count)
+    }
+
+    /// replaces `old` with `new` in place, keeping its weight and rotation state. Returns false if
+    /// `old` isn't present.
+    pub fn replace_item(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *old) {
+            Some(w) => {
+                w.item = new;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// zeroes the selection counters without touching the rotation state.
+    pub fn reset_counters(&mut self) {
+        for w in &mut self.items {
+            w.count = 0;
+        }
+    }
+
+    /// returns the item a freshly reset balancer would hand out for the `n`-th request (counting
+    /// from zero), without touching the rotation state.
+    ///
+    /// The result depends only on the items and their weights, so processes sharing the same
+    /// configuration and a request counter route identically without coordinating. A pick costs
+    /// O(log k + n) for k distinct weights, however large the weights are, once the pick table is
+    /// up to date; after changing the items call `rebalance()`, or every pick rebuilds the table.
+    pub fn roundrobin_pick_for(&self, n: u64) -> Option<&T> {
+        if self.dirty {
+            let (g, _) = self.compute_gcd_max();
+            if g <= 0 {
+                return None;
+            }
+            self.pick_in(&self.compute_bands(g), n)
+        } else {
+            self.pick_in(&self.bands, n)
+        }
+    }
+
+    fn pick_in(&self, bands: &[RoundBand], n: u64) -> Option<&T> {
+        let cycle = bands.last()?.end;
+        let n = u128::from(n) % cycle;
+
+        let band = bands.partition_point(|b| b.end <= n);
+        let start = if band == 0 { 0 } else { bands[band - 1].end };
+        let RoundBand { level, count, .. } = bands[band];
+        self.items
+            .iter()
+            .filter(|w| self.selection_weight(w.weight) >= level)
+            .map(|w| &w.item)
+            .nth(((n - start) % count as u128) as usize)
+    }
+
+    // advances the cursor to the next item passing `eligible`. The caller must make sure one
+    // with a positive weight exists, or that `eligible` accepts everything.
+    fn next_index(&mut self, mut eligible: impl FnMut(&T) -> bool) -> Option<usize> {
+        loop {
+            self.i = (self.i + 1) % (self.items.len() as isize);
+            if self.i == 0 {
+                self.cw -= self.gcd;
+                if self.cw <= 0 {
+                    self.cw = self.max_w;
+                    if self.cw == 0 {
+                        return None;
+                    }
                 }
             }
+
+            let w = &self.items[self.i as usize];
+            if self.selection_weight(w.weight) >= self.cw && eligible(&w.item) {
+                return Some(self.i as usize);
+            }
+        }
+    }
+
+    fn select(&mut self, index: usize) -> T {
+        if self.stats {
+            self.items[index].count += 1;
+        }
+        self.items[index].item.clone()
+    }
+}
+
+impl<T> RoundrobinWeight<T> {
+    /// consumes the balancer and yields its items with their weights in insertion order, moving
+    /// them out rather than cloning them, e.g. to hand them to another kind of balancer. The
+    /// position in the current round goes with the balancer.
+    pub fn into_items(self) -> impl ExactSizeIterator<Item = (T, isize)> {
+        self.items.into_iter().map(|w| (w.item, w.weight))
+    }
+}
+
+impl<T: Clone> Weight for RoundrobinWeight<T> {
+    fn add(&mut self, item: T, weight: isize) {
+        if self.limit.is_some_and(|max| self.items.len() >= max) {
+            return;
+        }
+
+        let weight_item = RRWeightItem {
+            item,
+            weight,
+            count: 0,
+        };
+
+        // start a fresh rotation if this brings the first selectable item
+        if self.selection_weight(weight) > 0 && !self.dirty && self.gcd == 0 {
+            self.i = -1;
+            self.cw = 0;
         }
 
         self.items.push(weight_item);
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let last = self.items.len() - 1;
+        let removed = self.items.swap_remove(index);
+
+        // the last item moves into the freed slot; if the cursor was on it, follow it there so the
+        // rotation carries on where it was
+        if self.i == last as isize {
+            self.i = if index == last {
+                self.i - 1
+            } else {
+                index as isize
+            };
+        }
+        self.dirty = true;
+
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => w.weight = weight,
+            None => return false,
+        }
+
+        self.dirty = true;
+        true
+    }
+
+    /// Scaling every weight by the same positive factor scales their gcd and maximum too, so the
+    /// sequence carries on unchanged. Otherwise a fresh rotation starts.
+    fn scale_weights(&mut self, factor: isize) {
+        for w in &mut self.items {
+            w.weight = w.weight.saturating_mul(factor);
+        }
+        self.dirty = true;
+        if factor > 0 {
+            self.cw = self.cw.saturating_mul(factor);
+        } else {
+            self.i = -1;
+            self.cw = 0;
+        }
     }
 
     fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
@@ -60,17 +418,151 @@ impl<T: Clone> Weight for RoundrobinWeight<T> {
             .map(|item| (item.item.clone(), item.weight))
     }
 
+    fn retain<F: FnMut(&T, isize) -> bool>(&mut self, mut f: F) {
+        let (cursor, mut index, mut shift) = (self.i, 0, 0);
+        self.items.retain(|w| {
+            let keep = f(&w.item, w.weight);
+            if !keep && index <= cursor {
+                shift += 1;
+            }
+            index += 1;
+            keep
+        });
+
+        self.i -= shift;
+        self.dirty = true;
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (T, isize)> + '_ {
+        let items = core::mem::take(&mut self.items);
+        self.remove_all();
+        items.into_iter().map(|w| (w.item, w.weight))
+    }
+
+    fn next_filtered<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Option<T> {
+        // without an eligible item that has a weight the cursor would spin forever
+        self.rebalance();
+        if !self
+            .items
+            .iter()
+            .any(|w| self.selection_weight(w.weight) > 0 && pred(&w.item))
+        {
+            return None;
+        }
+
+        let index = self.next_index(pred)?;
+        Some(self.select(index))
+    }
+
+    fn replace(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.replace_item(old, new)
+    }
+
     fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+        self.bands.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
         self.items.clear();
         self.gcd = 0;
         self.max_w = 0;
+        self.bands.clear();
         self.i = -1;
         self.cw = 0;
+        self.dirty = false;
     }
 
     fn reset(&mut self) {
         self.i = -1;
         self.cw = 0;
+        self.reset_counters();
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn max_weight(&self) -> Option<isize> {
+        // max_w already tracks the largest positive weight, unless a selection floor raised it
+        let (_, max_w) = self.gcd_max();
+        if self.floor == 0 && max_w > 0 {
+            return Some(max_w);
+        }
+        self.items.iter().map(|w| w.weight).max()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> FromIterator<(T, isize)> for RoundrobinWeight<T> {
+    fn from_iter<I: IntoIterator<Item = (T, isize)>>(iter: I) -> Self {
+        let mut w = RoundrobinWeight::new();
+        w.extend(iter);
+        w
+    }
+}
+
+/// Leaves gcd and max_w to the next selection, which computes them once for the whole batch.
+impl<T: Clone> Extend<(T, isize)> for RoundrobinWeight<T> {
+    fn extend<I: IntoIterator<Item = (T, isize)>>(&mut self, iter: I) {
+        // start a fresh rotation if this batch may bring the first selectable items, like `add`
+        if !self.dirty && self.gcd == 0 {
+            self.i = -1;
+            self.cw = 0;
+        }
+        let room = self
+            .limit
+            .map_or(usize::MAX, |max| max.saturating_sub(self.items.len()));
+        self.items.extend(
+            iter.into_iter()
+                .take(room)
+                .map(|(item, weight)| RRWeightItem {
+                    item,
+                    weight,
+                    count: 0,
+                }),
+        );
+
+        self.dirty = true;
+    }
+}
+
+/// Keeps the items with their weights, in the smooth balancer's stored order. Its current weights
+/// are dropped and the rotation begins with the first round.
+impl<T: Clone> From<SmoothWeight<T>> for RoundrobinWeight<T> {
+    fn from(w: SmoothWeight<T>) -> Self {
+        w.into_items().collect()
+    }
+}
+
+/// Keeps the items in insertion order with their weights and drops the random generator; an item
+/// limit set with `with_limit` isn't carried over either.
+#[cfg(feature = "random")]
+impl<T: Clone, R> From<RandWeight<T, R>> for RoundrobinWeight<T> {
+    fn from(w: RandWeight<T, R>) -> Self {
+        w.into_items().collect()
     }
 }
 
@@ -78,26 +570,18 @@ impl<T: Clone> Iterator for RoundrobinWeight<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        if self.items.len() <= 1 {
-            return self.items.first().map(|itme| itme.item.clone());
+        self.rebalance();
+        // max_w only counts positive weights, so this also covers an empty balancer and leaves
+        // the cursor untouched
+        if self.max_w == 0 {
+            return None;
         }
-
-        loop {
-            self.i = (self.i + 1) % (self.items.len() as isize);
-            if self.i == 0 {
-                self.cw -= self.gcd;
-                if self.cw <= 0 {
-                    self.cw = self.max_w;
-                    if self.cw == 0 {
-                        return None;
-                    }
-                }
-            }
-
-            if self.items[self.i as usize].weight >= self.cw {
-                return Some(self.items[self.i as usize].item.clone());
-            }
+        if self.items.len() == 1 {
+            return Some(self.select(0));
         }
+
+        let index = self.next_index(|_| true)?;
+        Some(self.select(index))
     }
 }
 
@@ -117,6 +601,27 @@ fn gcd(x: isize, y: isize) -> isize {
     }
 }
 
+/// Two balancers are equal when they hold the same `(item, weight)` pairs, in any order. The
+/// selection state, the counters and the settings are not compared.
+impl<T: PartialEq> PartialEq for RoundrobinWeight<T> {
+    fn eq(&self, other: &Self) -> bool {
+        same_items(
+            self.items.iter().map(|w| (&w.item, w.weight)),
+            other.items.iter().map(|w| (&w.item, w.weight)),
+        )
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for RoundrobinWeight<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_summary(
+            f,
+            "RoundrobinWeight",
+            self.items.iter().map(|w| (&w.item, w.weight)),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{RoundrobinWeight, Weight};
@@ -141,4 +646,349 @@ mod tests {
         assert_eq!(results["server2"], 20);
         assert_eq!(results["server3"], 30);
     }
+
+    #[test]
+    fn test_reset_counters() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        rrw.add("server3", 3);
+        rrw.enable_stats();
+
+        for _ in 0..10 {
+            rrw.next();
+        }
+        assert_eq!(rrw.selection_count(&"server1"), 5);
+
+        rrw.reset_counters();
+        assert_eq!(rrw.selection_count(&"server1"), 0);
+
+        // a full cycle after clearing still yields the configured ratio
+        for _ in 0..10 {
+            rrw.next();
+        }
+        assert_eq!(rrw.selection_count(&"server1"), 5);
+        assert_eq!(rrw.selection_count(&"server2"), 2);
+        assert_eq!(rrw.selection_count(&"server3"), 3);
+    }
+
+    #[test]
+    fn test_roundrobin_pick_for() {
+        for weights in [[5, 2, 3], [4, 6, 2], [1, 0, 3]].iter() {
+            let mut rrw: RoundrobinWeight<usize> = RoundrobinWeight::new();
+            for (i, w) in weights.iter().enumerate() {
+                rrw.add(i, *w);
+            }
+
+            let picks: Vec<usize> = (0..100)
+                .map(|n| *rrw.roundrobin_pick_for(n).unwrap())
+                .collect();
+            let expected: Vec<usize> = rrw.by_ref().take(100).collect();
+            assert_eq!(picks, expected);
+        }
+    }
+
+    #[test]
+    fn test_selection_floor() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 8);
+        rrw.add("server2", 1);
+        rrw.add("server3", 0);
+        rrw.set_selection_floor(2);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(rrw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 80);
+        assert_eq!(results["server2"], 20);
+        assert_eq!(results.get("server3"), None);
+        assert_eq!(*rrw.roundrobin_pick_for(4).unwrap(), "server2");
+
+        rrw.set_selection_floor(0);
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..90 {
+            *results.entry(rrw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 80);
+        assert_eq!(results["server2"], 10);
+    }
+
+    #[test]
+    fn test_extreme_weights() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", isize::MAX);
+        rrw.add("server2", isize::MAX);
+        rrw.add("server3", isize::MAX);
+        rrw.add("server4", isize::MIN);
+
+        let picks: Vec<&str> = rrw.by_ref().take(6).collect();
+        assert_eq!(
+            picks,
+            vec!["server1", "server2", "server3", "server1", "server2", "server3"]
+        );
+        assert_eq!(*rrw.roundrobin_pick_for(u64::MAX).unwrap(), "server1");
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        rrw.add("server3", 3);
+
+        for _ in 0..4 {
+            rrw.next();
+        }
+        assert_eq!(rrw.remove(&"server4"), None);
+        assert_eq!(rrw.all().count(), 3);
+
+        assert_eq!(rrw.remove(&"server2"), Some(("server2", 2)));
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..80 {
+            *results.entry(rrw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server2"), None);
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server3"], 30);
+    }
+
+    #[test]
+    fn test_update_weight() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        rrw.add("server3", 3);
+
+        assert!(!rrw.update_weight(&"server4", 1));
+        assert!(rrw.update_weight(&"server1", 8));
+        assert_eq!(rrw.gcd_max(), (1, 8));
+        assert!(rrw.update_weight(&"server3", 4));
+        assert!(rrw.update_weight(&"server1", 2));
+        assert_eq!(rrw.gcd_max(), (2, 4));
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..40 {
+            *results.entry(rrw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 10);
+        assert_eq!(results["server2"], 10);
+        assert_eq!(results["server3"], 20);
+    }
+
+    #[test]
+    fn test_remove_heaviest() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 4);
+        rrw.add("server2", 10);
+        rrw.add("server3", 6);
+        assert_eq!(rrw.gcd_max(), (2, 10));
+
+        rrw.next();
+        assert_eq!(rrw.remove(&"server2"), Some(("server2", 10)));
+        assert_eq!(rrw.gcd_max(), (2, 6));
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..50 {
+            *results.entry(rrw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 20);
+        assert_eq!(results["server3"], 30);
+    }
+
+    #[test]
+    fn test_next_filtered() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 5);
+        rrw.add("server2", 2);
+        rrw.add("server3", 3);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..50 {
+            let s = rrw.next_filtered(|s| *s != "server1").unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server1"), None);
+        assert_eq!(results["server2"], 20);
+        assert_eq!(results["server3"], 30);
+
+        assert_eq!(rrw.next_filtered(|_| false), None);
+    }
+
+    #[test]
+    fn test_all_zero_weights() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 0);
+        assert_eq!(rrw.next(), None);
+
+        rrw.add("server2", 0);
+        rrw.add("server3", 0);
+        let (i, cw) = (rrw.i, rrw.cw);
+        for _ in 0..10 {
+            assert_eq!(rrw.next(), None);
+        }
+        assert_eq!((rrw.i, rrw.cw), (i, cw));
+
+        rrw.update_weight(&"server2", 1);
+        assert_eq!(rrw.next(), Some("server2"));
+        rrw.update_weight(&"server2", 0);
+        assert_eq!(rrw.next(), None);
+        assert_eq!(rrw.roundrobin_pick_for(0), None);
+    }
+
+    #[test]
+    fn test_clear_items() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 6);
+        rrw.add("server2", 4);
+        rrw.next();
+
+        rrw.clear_items();
+        assert_eq!((rrw.gcd, rrw.max_w, rrw.i, rrw.cw), (0, 0, -1, 0));
+        assert_eq!(rrw.next(), None);
+
+        rrw.add("server3", 3);
+        rrw.add("server4", 1);
+        assert_eq!(
+            rrw.next_n(4),
+            vec!["server3", "server3", "server3", "server4"]
+        );
+    }
+
+    #[test]
+    fn test_take_weighted() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        assert_eq!(rrw.take_weighted(10).count(), 0);
+
+        rrw.add("server1", 2);
+        rrw.add("server2", 1);
+        let picks: Vec<String> = rrw.take_weighted(4).map(|s| s.to_uppercase()).collect();
+        assert_eq!(picks, vec!["SERVER1", "SERVER1", "SERVER2", "SERVER1"]);
+
+        // the balancer is still usable and carries on where the adaptor stopped
+        assert_eq!(rrw.next(), Some("server1"));
+    }
+
+    #[test]
+    fn test_initial_cursor() {
+        let build = |mut rrw: RoundrobinWeight<&'static str>| {
+            rrw.add("server1", 0);
+            rrw.update_weight(&"server1", 2);
+            rrw.add("server2", 2);
+            rrw
+        };
+
+        let mut fresh = build(RoundrobinWeight::new());
+        let mut defaulted = build(RoundrobinWeight::default());
+        let mut reset = build(RoundrobinWeight::new());
+        reset.next_n(3);
+        reset.reset();
+        let mut cleared = build(RoundrobinWeight::new());
+        cleared.next_n(3);
+        cleared.remove_all();
+        let mut cleared = build(cleared);
+
+        let expected = fresh.next_n(4);
+        assert_eq!(expected, vec!["server1", "server2", "server1", "server2"]);
+        assert_eq!(defaulted.next_n(4), expected);
+        assert_eq!(reset.next_n(4), expected);
+        assert_eq!(cleared.next_n(4), expected);
+    }
+
+    #[test]
+    fn test_needs_rebalance() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 4);
+        rrw.add("server2", 6);
+        assert!(rrw.needs_rebalance());
+        rrw.rebalance();
+        assert!(!rrw.needs_rebalance());
+
+        rrw.update_weight(&"server2", 2);
+        rrw.remove(&"server1");
+        assert!(rrw.needs_rebalance());
+        assert_eq!((rrw.gcd, rrw.max_w), (2, 6));
+        assert_eq!(rrw.max_weight(), Some(2));
+        assert_eq!(rrw.roundrobin_pick_for(0), Some(&"server2"));
+
+        rrw.add("server3", 1);
+        assert_eq!(rrw.next(), Some("server2"));
+        assert!(!rrw.needs_rebalance());
+        assert_eq!((rrw.gcd, rrw.max_w), (1, 2));
+
+        rrw.extend(vec![("server4", 3)]);
+        assert!(rrw.needs_rebalance());
+        rrw.next_filtered(|s| *s != "server4");
+        assert!(!rrw.needs_rebalance());
+        assert_eq!((rrw.gcd, rrw.max_w), (1, 3));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 2);
+        rrw.add("server2", 1);
+        rrw.next();
+
+        let mut copy = rrw.clone();
+        assert_eq!(copy.next_n(3), rrw.next_n(3));
+        copy.remove(&"server2");
+        assert_eq!(rrw.len(), 2);
+    }
+
+    #[test]
+    fn test_from_weights() {
+        let weights: Vec<(usize, isize)> = (0..1000).map(|i| (i, (i % 7) as isize * 6)).collect();
+        let mut rrw = RoundrobinWeight::from_weights(weights.clone());
+        assert!(!rrw.needs_rebalance());
+        assert_eq!((rrw.gcd, rrw.max_w), (6, 36));
+        assert_eq!(rrw.len(), 1000);
+
+        let mut added: RoundrobinWeight<usize> = RoundrobinWeight::new();
+        for (item, weight) in weights {
+            added.add(item, weight);
+        }
+        assert_eq!(rrw.next_n(500), added.next_n(500));
+        assert!(RoundrobinWeight::<&str>::from_weights(vec![])
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_pick_for_large_weights() {
+        let mut rrw = RoundrobinWeight::from_weights(vec![("server1", 1_000_000), ("server2", 1)]);
+        assert_eq!(rrw.roundrobin_pick_for(0), Some(&"server1"));
+        assert_eq!(rrw.roundrobin_pick_for(999_999), Some(&"server1"));
+        assert_eq!(rrw.roundrobin_pick_for(1_000_000), Some(&"server2"));
+        assert_eq!(rrw.roundrobin_pick_for(1_000_001), Some(&"server1"));
+
+        // the table follows changes once they are applied, and matches `next()`
+        rrw.extend(vec![("server3", 500_000), ("server4", 0)]);
+        rrw.update_weight(&"server1", 1_500_000);
+        rrw.rebalance();
+        assert_eq!(rrw.bands.len(), 3);
+        let n = 1_500_000 + 2 * 500_000;
+        let picks: Vec<&str> = (n - 4..n + 2)
+            .map(|n| *rrw.roundrobin_pick_for(n).unwrap())
+            .collect();
+        let expected: Vec<&str> = rrw.by_ref().skip(n as usize - 4).take(6).collect();
+        assert_eq!(picks, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_older_formats() {
+        // fields added since are filled in with their defaults
+        let jsons = [
+            r#"{"items":[{"item":"a","weight":2,"count":0},{"item":"b","weight":1,"count":0}],
+                "gcd":1,"max_w":2,"i":-1,"cw":0,"stats":false,"floor":0}"#,
+            r#"{"items":[{"item":"a","weight":2},{"item":"b","weight":1}],
+                "gcd":1,"max_w":2,"i":-1,"cw":0}"#,
+        ];
+        for json in jsons.iter() {
+            let mut rrw: RoundrobinWeight<String> = serde_json::from_str(json).unwrap();
+            assert_eq!(rrw.limit(), None);
+            assert_eq!(rrw.next_n(3), vec!["a", "a", "b"]);
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use super::{Weight, WeightError};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// SharedWeight wraps any balancer in an `Arc<Mutex<_>>` so it can be shared between threads and
+/// used through `&self`.
+///
+/// Cloning a `SharedWeight` is cheap and every clone drives the same balancer. Each call, `next()`
+/// included, takes the lock for just that call. A poisoned lock is recovered rather than
+/// propagated, like in `AtomicRoundrobin`.
+#[derive(Debug, Default)]
+pub struct SharedWeight<W> {
+    inner: Arc<Mutex<W>>,
+}
+
+impl<W> Clone for SharedWeight<W> {
+    fn clone(&self) -> Self {
+        SharedWeight {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<W: Weight> SharedWeight<W> {
+    pub fn new(inner: W) -> Self {
+        SharedWeight {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, W> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// runs `f` with the balancer locked, for anything not covered by the methods below.
+    pub fn with_inner<U>(&self, f: impl FnOnce(&mut W) -> U) -> U {
+        f(&mut self.lock())
+    }
+
+    /// selects the next item, briefly taking the lock.
+    pub fn next(&self) -> Option<W::Item> {
+        self.lock().next()
+    }
+
+    /// adds a weighted item for selection.
+    pub fn add(&self, item: W::Item, weight: isize) {
+        self.lock().add(item, weight);
+    }
+
+    /// adds a weighted item for selection, or returns an error if the weight is not positive.
+    pub fn try_add(&self, item: W::Item, weight: isize) -> Result<(), WeightError> {
+        self.lock().try_add(item, weight)
+    }
+
+    /// removes a weighted item and returns it with its weight, or `None` if it isn't present.
+    pub fn remove(&self, item: &W::Item) -> Option<(W::Item, isize)>
+    where
+        W::Item: PartialEq,
+    {
+        self.lock().remove(item)
+    }
+
+    /// changes the weight of an existing item in place, returning false if it isn't present.
+    pub fn update_weight(&self, item: &W::Item, weight: isize) -> bool
+    where
+        W::Item: PartialEq,
+    {
+        self.lock().update_weight(item, weight)
+    }
+
+    /// returns all items.
+    pub fn all(&self) -> Vec<(W::Item, isize)> {
+        self.lock().all().collect()
+    }
+
+    /// removes all weighted items.
+    pub fn remove_all(&self) {
+        self.lock().remove_all();
+    }
+
+    /// removes all weighted items, keeping the allocated storage.
+    pub fn clear_items(&self) {
+        self.lock().clear_items();
+    }
+
+    /// resets the balancing algorithm.
+    pub fn reset(&self) {
+        self.lock().reset();
+    }
+
+    /// returns the number of items.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// returns true if there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// returns true if `item` is present.
+    pub fn contains(&self, item: &W::Item) -> bool
+    where
+        W::Item: PartialEq,
+    {
+        self.lock().contains(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SharedWeight, SmoothWeight};
+    use std::collections::HashMap;
+    use std::thread;
+
+    #[test]
+    fn test_shared_weight() {
+        let shared = SharedWeight::new(SmoothWeight::new());
+        shared.add("server1", 5);
+        shared.add("server2", 2);
+        shared.add("server3", 3);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || (0..250).map(|_| shared.next().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for handle in handles {
+            for item in handle.join().unwrap() {
+                *results.entry(item).or_insert(0) += 1;
+            }
+        }
+
+        // every selection goes through the same balancer, so whole cycles stay exact
+        assert_eq!(results["server1"], 500);
+        assert_eq!(results["server2"], 200);
+        assert_eq!(results["server3"], 300);
+
+        assert!(shared.update_weight(&"server2", 0));
+        assert_eq!(shared.remove(&"server3"), Some(("server3", 3)));
+        assert_eq!(shared.len(), 2);
+    }
+}
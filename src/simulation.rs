@@ -0,0 +1,64 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// The outcome of drawing a number of selections from a balancer, see `Weight::simulate`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Simulation<T: Eq + Hash> {
+    /// how many times each item was selected.
+    pub counts: HashMap<T, usize>,
+    /// the longest run of back-to-back selections of each item.
+    pub max_consecutive: HashMap<T, usize>,
+}
+
+impl<T: Eq + Hash + Clone> Simulation<T> {
+    /// tallies a sequence of selections in a single pass.
+    pub fn from_selections<I: IntoIterator<Item = T>>(selections: I) -> Self {
+        let mut sim = Simulation {
+            counts: HashMap::new(),
+            max_consecutive: HashMap::new(),
+        };
+
+        let mut last: Option<T> = None;
+        let mut run = 0;
+        for item in selections {
+            *sim.counts.entry(item.clone()).or_insert(0) += 1;
+
+            if last.as_ref() == Some(&item) {
+                run += 1;
+            } else {
+                run = 1;
+            }
+            let longest = sim.max_consecutive.entry(item.clone()).or_insert(0);
+            if *longest < run {
+                *longest = run;
+            }
+            last = Some(item);
+        }
+
+        sim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RoundrobinWeight, SmoothWeight, Weight};
+
+    #[test]
+    fn test_simulate() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        for w in [("a", 5), ("b", 1), ("c", 1)].iter() {
+            sw.add(w.0, w.1);
+            rrw.add(w.0, w.1);
+        }
+
+        let smooth = sw.simulate(70);
+        let lvs = rrw.simulate(70);
+        assert_eq!(smooth.counts, lvs.counts);
+        assert_eq!(smooth.counts["a"], 50);
+
+        // smooth breaks up the heavy item while LVS emits a whole cycle of it in a row
+        assert_eq!(smooth.max_consecutive["a"], 4);
+        assert_eq!(lvs.max_consecutive["a"], 5);
+        assert_eq!(lvs.max_consecutive["b"], 1);
+    }
+}
@@ -1,11 +1,42 @@
-use super::Weight;
+#[cfg(feature = "random")]
+use super::RandWeight;
+use super::{display_summary, rotation, same_items, RoundrobinWeight, Weight};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::iter::FromIterator;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct SmoothWeightItem<T> {
     item: T,
     weight: isize,
-    current_weight: isize,
+    current_weight: i128,
     effective_weight: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    count: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    cap: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    in_flight: usize,
+}
+
+impl<T> SmoothWeightItem<T> {
+    fn new(item: T, weight: isize, cap: Option<usize>) -> Self {
+        SmoothWeightItem {
+            item,
+            weight,
+            current_weight: 0,
+            effective_weight: weight,
+            count: 0,
+            cap,
+            in_flight: 0,
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.cap.is_none_or(|cap| self.in_flight < cap)
+    }
 }
 
 /// SW (Smooth Weighted) is a struct that contains weighted items and provides methods to select a
@@ -18,18 +49,246 @@ struct SmoothWeightItem<T> {
 ///
 /// In case of { 5, 1, 1 } weights this gives the following sequence of
 /// current_weight's: (a, a, b, a, c, a, a)
-#[derive(Default)]
+///
+/// Current weights are accumulated as `i128`, so weights up to `isize::MAX` can't overflow them.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SmoothWeight<T> {
     items: Vec<SmoothWeightItem<T>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    stats: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    floor: isize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    limit: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    order: Option<fn(&T, &T) -> Ordering>,
 }
 
 impl<T: Clone> SmoothWeight<T> {
     pub const fn new() -> Self {
-        SmoothWeight { items: Vec::new() }
+        SmoothWeight {
+            items: Vec::new(),
+            stats: false,
+            floor: 0,
+            limit: None,
+            order: None,
+        }
+    }
+
+    /// creates an empty balancer with room for `capacity` items before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SmoothWeight {
+            items: Vec::with_capacity(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// creates an empty balancer that holds at most `max` items. Adding more is refused, while
+    /// the weights of items already present can still be updated.
+    pub fn with_limit(max: usize) -> Self {
+        SmoothWeight {
+            limit: Some(max),
+            ..Self::new()
+        }
+    }
+
+    /// treats every positive weight as at least `floor` when selecting, so light items keep a
+    /// minimum share next to heavy ones. The configured weights are left untouched and a floor of
+    /// 0 turns this off again.
+    pub fn set_selection_floor(&mut self, floor: isize) {
+        self.floor = floor;
+    }
+
+    fn selection_weight(&self, index: usize) -> isize {
+        let item = &self.items[index];
+        if item.weight > 0 {
+            item.effective_weight.max(self.floor)
+        } else {
+            item.effective_weight
+        }
+    }
+
+    /// enables counting how many times each item is selected.
+    pub fn enable_stats(&mut self) {
+        self.stats = true;
+    }
+
+    /// returns how many times `item` has been selected while counting was enabled, since the last
+    /// `reset()` or `reset_counters()`.
+    pub fn selection_count(&self, item: &T) -> usize
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .map_or(0, |w| w.count)
+    }
+
+    /// replaces `old` with `new` in place, keeping its weight and rotation state. Returns false if
+    /// `old` isn't present.
+    pub fn replace_item(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        match self.items.iter_mut().find(|w| w.item == *old) {
+            Some(w) => {
+                w.item = new;
+                self.sort_items();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// returns every item with its `weight`, `current_weight`, `effective_weight` and the cap
+    /// set with `add_with_cap`, enough for `restore` to carry the rotation on exactly where it is.
+    /// The current weight is an `i128` like it is internally, so nothing is truncated.
+    pub fn snapshot(&self) -> Vec<(T, isize, i128, isize, Option<usize>)> {
+        self.items
+            .iter()
+            .map(|w| {
+                (
+                    w.item.clone(),
+                    w.weight,
+                    w.current_weight,
+                    w.effective_weight,
+                    w.cap,
+                )
+            })
+            .collect()
+    }
+
+    /// returns `(item, weight, current_weight, effective_weight)` for every item, to see what
+    /// drives the selection when a distribution looks off. This is for reading only; use
+    /// `snapshot` to carry the state over to another balancer. A current weight beyond the range
+    /// of `isize` is clamped to it.
+    pub fn debug_state(&self) -> Vec<(T, isize, isize, isize)> {
+        self.items
+            .iter()
+            .map(|w| {
+                let current_weight = w
+                    .current_weight
+                    .clamp(isize::MIN as i128, isize::MAX as i128);
+                (
+                    w.item.clone(),
+                    w.weight,
+                    current_weight as isize,
+                    w.effective_weight,
+                )
+            })
+            .collect()
+    }
+
+    /// replaces all items, their caps and their rotation state with a `snapshot`. Selection
+    /// counters start from 0, and so does the number of acquired items in flight.
+    pub fn restore(&mut self, snapshot: Vec<(T, isize, i128, isize, Option<usize>)>) {
+        self.items = snapshot
+            .into_iter()
+            .map(
+                |(item, weight, current_weight, effective_weight, cap)| SmoothWeightItem {
+                    current_weight,
+                    effective_weight,
+                    ..SmoothWeightItem::new(item, weight, cap)
+                },
+            )
+            .collect();
+        self.sort_items();
+    }
+
+    /// adds an item that is handed out by `acquire` at most `cap` times until it's `release`d. While
+    /// it's at capacity it sits the rounds out, in `next()` too, and the other items share its
+    /// traffic.
+    pub fn add_with_cap(&mut self, item: T, weight: isize, cap: usize) {
+        self.push_item(SmoothWeightItem::new(item, weight, Some(cap)));
+    }
+
+    /// selects the next item with room left, like `next()`, and counts it as in flight until it's
+    /// passed to `release`. Returns `None` if every item with a weight is at capacity.
+    pub fn acquire(&mut self) -> Option<&T> {
+        let index = self.next_smooth_weighted(|_| true)?;
+        if self.stats {
+            self.items[index].count += 1;
+        }
+        self.items[index].in_flight += 1;
+        Some(&self.items[index].item)
+    }
+
+    /// hands back an item taken with `acquire`, making room for another one. Releasing an item
+    /// with nothing in flight is a no-op.
+    pub fn release(&mut self, item: &T)
+    where
+        T: PartialEq,
+    {
+        if let Some(w) = self.items.iter_mut().find(|w| w.item == *item) {
+            w.in_flight = w.in_flight.saturating_sub(1);
+        }
+    }
+
+    /// returns how many times `item` has been acquired and not yet released.
+    pub fn in_flight(&self, item: &T) -> usize
+    where
+        T: PartialEq,
+    {
+        self.items
+            .iter()
+            .find(|w| w.item == *item)
+            .map_or(0, |w| w.in_flight)
+    }
+
+    /// zeroes the selection counters without touching the rotation state.
+    pub fn reset_counters(&mut self) {
+        for w in &mut self.items {
+            w.count = 0;
+        }
+    }
+
+    /// reports the outcome of a request sent to `item`. A failure takes half of its weight off its
+    /// effective weight, never going below 1, so it's chosen less for a while; every selection
+    /// round then restores 1 point until it's back at its configured weight, like Nginx does.
+    /// Success needs no action.
+    pub fn feedback(&mut self, item: &T, success: bool)
+    where
+        T: PartialEq,
+    {
+        if success {
+            return;
+        }
+
+        if let Some(w) = self.items.iter_mut().find(|w| w.item == *item) {
+            if w.weight > 0 {
+                w.effective_weight = (w.effective_weight - (w.weight / 2).max(1)).max(1);
+            }
+        }
+    }
+
+    /// resets the rotation state of `item` alone, e.g. once it has recovered from an outage: its
+    /// current weight goes back to 0 and its effective weight to its configured weight, undoing
+    /// any `feedback`. The current weight it had is handed to the other items in proportion to
+    /// their weights, like `remove` does, so the current weights keep summing to zero. Returns
+    /// false if `item` isn't present.
+    pub fn reset_item(&mut self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let index = match self.items.iter().position(|w| w.item == *item) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let mut w = self.items.remove(index);
+        self.redistribute(w.current_weight);
+        w.current_weight = 0;
+        w.effective_weight = w.weight;
+        self.items.insert(index, w);
+        true
     }
 
     //https://github.com/phusion/nginx/commit/27e94984486058d73157038f7950a0a36ecc6e35
-    fn next_smooth_weighted(&mut self) -> Option<SmoothWeightItem<T>> {
+    // items rejected by `eligible` or at capacity sit the round out, as Nginx does for peers marked
+    // down, so their current weight neither grows nor counts toward the total
+    fn next_smooth_weighted(&mut self, mut eligible: impl FnMut(&T) -> bool) -> Option<usize> {
         let mut total = 0;
 
         let mut best_index = 0;
@@ -37,8 +296,15 @@ impl<T: Clone> SmoothWeight<T> {
 
         let items_len = self.items.len();
         for i in 0..items_len {
-            self.items[i].current_weight += self.items[i].effective_weight;
-            total += self.items[i].effective_weight;
+            // zero-weight items are registered but never selected
+            let weight = self.selection_weight(i) as i128;
+            let w = &self.items[i];
+            if weight <= 0 || !w.has_room() || !eligible(&w.item) {
+                continue;
+            }
+
+            self.items[i].current_weight += weight;
+            total += weight;
             if self.items[i].effective_weight < self.items[i].weight {
                 self.items[i].effective_weight += 1;
             }
@@ -54,20 +320,179 @@ impl<T: Clone> SmoothWeight<T> {
         }
 
         self.items[best_index].current_weight -= total;
-        Some(self.items[best_index].clone())
+        Some(best_index)
+    }
+
+    /// returns the item the next call to `next()` would select, without advancing any state.
+    pub fn peek(&self) -> Option<&T> {
+        let mut best: Option<(usize, i128)> = None;
+        for i in 0..self.items.len() {
+            let weight = self.selection_weight(i) as i128;
+            if weight <= 0 || !self.items[i].has_room() {
+                continue;
+            }
+
+            let current_weight = self.items[i].current_weight + weight;
+            if best.is_none_or(|(_, best_weight)| current_weight > best_weight) {
+                best = Some((i, current_weight));
+            }
+        }
+
+        best.map(|(i, _)| &self.items[i].item)
+    }
+
+    // keeps the items of a `new_ordered` balancer sorted; a stable sort of a nearly sorted vec is
+    // close to linear
+    fn sort_items(&mut self) {
+        if let Some(cmp) = self.order {
+            self.items
+                .sort_by(|a, b| b.weight.cmp(&a.weight).then_with(|| cmp(&a.item, &b.item)));
+        }
+    }
+
+    fn redistribute(&mut self, amount: i128) {
+        rotation::redistribute(&mut self.items, amount, |w| {
+            Some((w.weight, &mut w.current_weight))
+        });
+    }
+
+    fn push_item(&mut self, item: SmoothWeightItem<T>) {
+        if self.limit.is_some_and(|max| self.items.len() >= max) {
+            return;
+        }
+
+        self.items.push(item);
+        self.sort_items();
+    }
+
+    fn select(&mut self, index: usize) -> T {
+        if self.stats {
+            self.items[index].count += 1;
+        }
+        self.items[index].item.clone()
+    }
+}
+
+impl<T: Clone + Ord> SmoothWeight<T> {
+    /// creates an empty balancer that keeps its items sorted by descending weight, then by item,
+    /// instead of in insertion order. The same items and weights then always produce the same
+    /// selection sequence, however they were added, e.g. while iterating a `HashMap`.
+    ///
+    /// The sequence differs from the one an insertion-ordered balancer emits, since ties between
+    /// equal current weights go to the item sorted first. Removal keeps the order by shifting the
+    /// later items, which is O(n) like the scan that finds the item. The ordering isn't
+    /// serialized; a deserialized balancer keeps insertion order.
+    pub fn new_ordered() -> Self {
+        SmoothWeight {
+            order: Some(T::cmp),
+            ..Self::new()
+        }
+    }
+}
+
+impl<T> SmoothWeight<T> {
+    /// consumes the balancer and yields its items with their weights in stored order, heaviest
+    /// first for a `new_ordered` balancer, moving them out rather than cloning them.
+    ///
+    /// The balancer is itself an `Iterator` of selections, so it can't also implement
+    /// `IntoIterator` over its items; `for item in balancer` keeps meaning "draw from it".
+    pub fn into_items(self) -> impl ExactSizeIterator<Item = (T, isize)> {
+        self.items.into_iter().map(|w| (w.item, w.weight))
     }
 }
 
 impl<T: Clone> Weight for SmoothWeight<T> {
     fn add(&mut self, item: T, weight: isize) {
-        let weight_item = SmoothWeightItem {
-            item,
-            weight,
-            current_weight: 0,
-            effective_weight: weight,
+        self.push_item(SmoothWeightItem::new(item, weight, None));
+    }
+
+    /// The current weights always sum to zero between selections. The removed item takes its
+    /// current weight with it, so that amount is handed back to the remaining items in proportion
+    /// to their weights. This keeps their relative phase and avoids a burst of selections toward
+    /// whichever item happened to be ahead.
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = if self.order.is_some() {
+            self.items.remove(index)
+        } else {
+            self.items.swap_remove(index)
         };
+        self.redistribute(removed.current_weight);
+        Some((removed.item, removed.weight))
+    }
 
-        self.items.push(weight_item);
+    /// The accumulated `current_weight` is rescaled by `new / old` so the item keeps the same
+    /// relative position in its cycle; leaving it as is would make a lowered weight burst with
+    /// selections earned at the old rate, and a raised one starve for a while. The amount the
+    /// rescale frees or claims is spread over all items in proportion to their weights, keeping
+    /// the current weights summing to zero. An item whose old weight wasn't positive restarts at
+    /// 0.
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        let w = match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => w,
+            None => return false,
+        };
+
+        let old_current = w.current_weight;
+        let new_current = rotation::rescale(old_current, w.weight, weight);
+        w.weight = weight;
+        w.effective_weight = weight;
+        w.current_weight = new_current;
+
+        self.redistribute(old_current - new_current);
+        self.sort_items();
+        true
+    }
+
+    /// The current and effective weights are scaled along with the weights, so with a positive
+    /// factor the rotation carries on exactly where it was. Otherwise they start from scratch.
+    fn scale_weights(&mut self, factor: isize) {
+        for w in &mut self.items {
+            w.weight = w.weight.saturating_mul(factor);
+            if factor > 0 {
+                w.effective_weight = w.effective_weight.saturating_mul(factor);
+                w.current_weight = w.current_weight.saturating_mul(factor as i128);
+            } else {
+                w.effective_weight = w.weight;
+                w.current_weight = 0;
+            }
+        }
+        self.sort_items();
+    }
+
+    /// The items swap their whole rotation state along with the weights, so each one carries on
+    /// exactly where the other left off and the traffic flips right away, with no burst while the
+    /// current weights adjust.
+    fn swap_weights(&mut self, a: &T, b: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let ia = match self.items.iter().position(|w| w.item == *a) {
+            Some(i) => i,
+            None => return false,
+        };
+        let ib = match self.items.iter().position(|w| w.item == *b) {
+            Some(i) => i,
+            None => return false,
+        };
+
+        let (wa, wb) = (&self.items[ia], &self.items[ib]);
+        let (sa, sb) = (
+            (wa.weight, wa.current_weight, wa.effective_weight),
+            (wb.weight, wb.current_weight, wb.effective_weight),
+        );
+        let w = &mut self.items[ia];
+        (w.weight, w.current_weight, w.effective_weight) = sb;
+        let w = &mut self.items[ib];
+        (w.weight, w.current_weight, w.effective_weight) = sa;
+        self.sort_items();
+        true
     }
 
     fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
@@ -76,7 +501,42 @@ impl<T: Clone> Weight for SmoothWeight<T> {
             .map(|item| (item.item.clone(), item.weight))
     }
 
+    fn retain<F: FnMut(&T, isize) -> bool>(&mut self, mut f: F) {
+        let mut removed = 0;
+        self.items.retain(|w| {
+            let keep = f(&w.item, w.weight);
+            if !keep {
+                removed += w.current_weight;
+            }
+            keep
+        });
+        self.redistribute(removed);
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = (T, isize)> + '_ {
+        let items = core::mem::take(&mut self.items);
+        self.remove_all();
+        items.into_iter().map(|w| (w.item, w.weight))
+    }
+
+    fn next_filtered<F: FnMut(&T) -> bool>(&mut self, pred: F) -> Option<T> {
+        let index = self.next_smooth_weighted(pred)?;
+        Some(self.select(index))
+    }
+
+    fn replace(&mut self, old: &T, new: T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.replace_item(old, new)
+    }
+
     fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
         self.items.clear();
     }
 
@@ -84,20 +544,151 @@ impl<T: Clone> Weight for SmoothWeight<T> {
         for w in &mut self.items {
             w.current_weight = 0;
             w.effective_weight = w.weight;
+            w.count = 0;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> FromIterator<(T, isize)> for SmoothWeight<T> {
+    fn from_iter<I: IntoIterator<Item = (T, isize)>>(iter: I) -> Self {
+        let mut w = SmoothWeight::new();
+        w.extend(iter);
+        w
+    }
+}
+
+impl<T: Clone> Extend<(T, isize)> for SmoothWeight<T> {
+    fn extend<I: IntoIterator<Item = (T, isize)>>(&mut self, iter: I) {
+        for (item, weight) in iter {
+            self.add(item, weight);
         }
     }
 }
 
+/// Keeps the items in insertion order with their weights. The position in the round isn't
+/// translated, so the smooth sequence starts from zero current weights.
+impl<T: Clone> From<RoundrobinWeight<T>> for SmoothWeight<T> {
+    fn from(w: RoundrobinWeight<T>) -> Self {
+        w.into_items().collect()
+    }
+}
+
+/// Keeps the items with their weights and drops the random generator, so selection turns
+/// deterministic.
+#[cfg(feature = "random")]
+impl<T: Clone, R> From<RandWeight<T, R>> for SmoothWeight<T> {
+    fn from(w: RandWeight<T, R>) -> Self {
+        w.into_items().collect()
+    }
+}
+
 impl<T: Clone> Iterator for SmoothWeight<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
-        if self.items.len() <= 1 {
-            return self.items.first().map(|item| item.item.clone());
+        if self.items.len() == 1 {
+            if self.selection_weight(0) <= 0 || !self.items[0].has_room() {
+                return None;
+            }
+            return Some(self.select(0));
         }
 
-        let rt = self.next_smooth_weighted()?;
-        Some(rt.item)
+        let index = self.next_smooth_weighted(|_| true)?;
+        Some(self.select(index))
+    }
+}
+
+/// Two balancers are equal when they hold the same `(item, weight)` pairs, in any order. The
+/// selection state, the counters and the settings are not compared.
+impl<T: PartialEq> PartialEq for SmoothWeight<T> {
+    fn eq(&self, other: &Self) -> bool {
+        same_items(
+            self.items.iter().map(|w| (&w.item, w.weight)),
+            other.items.iter().map(|w| (&w.item, w.weight)),
+        )
+    }
+}
+
+/// Shows each item's `weight`, `current_weight` and `effective_weight`, which is what matters
+/// when a distribution looks off. Selection counts are included once stats are enabled, and the
+/// in-flight count of items added with a cap.
+impl<T: fmt::Debug> fmt::Debug for SmoothWeight<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct State<'a, T>(&'a SmoothWeightItem<T>, bool);
+
+        impl<T> fmt::Debug for State<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut s = f.debug_struct("Item");
+                s.field("weight", &self.0.weight)
+                    .field("current_weight", &self.0.current_weight)
+                    .field("effective_weight", &self.0.effective_weight);
+                if self.1 {
+                    s.field("count", &self.0.count);
+                }
+                if let Some(cap) = self.0.cap {
+                    s.field("cap", &cap).field("in_flight", &self.0.in_flight);
+                }
+                s.finish()
+            }
+        }
+
+        struct Items<'a, T>(&'a SmoothWeight<T>);
+
+        impl<T: fmt::Debug> fmt::Debug for Items<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_map()
+                    .entries(
+                        self.0
+                            .items
+                            .iter()
+                            .map(|w| (&w.item, State(w, self.0.stats))),
+                    )
+                    .finish()
+            }
+        }
+
+        let mut s = f.debug_struct("SmoothWeight");
+        s.field("items", &Items(self));
+        if self.floor != 0 {
+            s.field("floor", &self.floor);
+        }
+        if let Some(limit) = self.limit {
+            s.field("limit", &limit);
+        }
+        s.finish()
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SmoothWeight<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display_summary(
+            f,
+            "SmoothWeight",
+            self.items.iter().map(|w| (&w.item, w.weight)),
+        )
     }
 }
 
@@ -125,4 +716,574 @@ mod tests {
         assert_eq!(results["server2"], 20);
         assert_eq!(results["server3"], 30);
     }
+
+    #[test]
+    fn test_reset_counters() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        let mut other: SmoothWeight<&str> = SmoothWeight::new();
+        for w in [&mut sw, &mut other].iter_mut() {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+        }
+        sw.enable_stats();
+
+        for _ in 0..10 {
+            assert_eq!(sw.next(), other.next());
+        }
+        assert_eq!(sw.selection_count(&"server1"), 5);
+        assert_eq!(sw.selection_count(&"server2"), 2);
+
+        sw.reset_counters();
+        assert_eq!(sw.selection_count(&"server1"), 0);
+
+        // the rotation continues exactly where it left off
+        for _ in 0..7 {
+            assert_eq!(sw.next(), other.next());
+        }
+        assert_eq!(sw.selection_count(&"server1"), 4);
+    }
+
+    #[test]
+    fn test_remove_keeps_fairness() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+
+        for _ in 0..3 {
+            sw.next();
+        }
+
+        assert_eq!(sw.remove(&"server1"), Some(("server1", 5)));
+        assert_eq!(sw.remove(&"server1"), None);
+        assert_eq!(sw.items.iter().map(|w| w.current_weight).sum::<i128>(), 0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..5 {
+            let s = sw.next().unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results["server2"], 2);
+        assert_eq!(results["server3"], 3);
+
+        for _ in 0..45 {
+            let s = sw.next().unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results["server2"], 20);
+        assert_eq!(results["server3"], 30);
+    }
+
+    #[test]
+    fn test_contains_with_weight() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+
+        assert!(sw.contains_with_weight(&"server1", 5));
+        assert!(!sw.contains_with_weight(&"server1", 2));
+        assert!(!sw.contains_with_weight(&"server3", 5));
+    }
+
+    #[test]
+    fn test_next_with_affinity() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+
+        let healthy = |s: &&str| *s != "server2";
+        for _ in 0..10 {
+            assert_eq!(
+                sw.next_with_affinity(Some(&"server2"), &|_| true),
+                Some("server2")
+            );
+            assert_eq!(
+                sw.next_with_affinity(Some(&"server1"), &healthy),
+                Some("server1")
+            );
+        }
+
+        // an unhealthy or unknown previous choice falls back to a normal selection
+        assert_eq!(
+            sw.next_with_affinity(Some(&"server2"), &healthy),
+            Some("server1")
+        );
+        assert_eq!(
+            sw.next_with_affinity(Some(&"server9"), &healthy),
+            Some("server2")
+        );
+        assert_eq!(sw.next_with_affinity(None, &healthy), Some("server1"));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("a", 5);
+        sw.add("b", 2);
+        sw.add("c", 3);
+
+        assert_eq!(
+            sw.to_string(),
+            "SmoothWeight{items=3, total=10, [a:5 b:2 c:3]}"
+        );
+        assert_eq!(
+            format!("{:.2}", sw),
+            "SmoothWeight{items=3, total=10, [a:5 b:2 ...]}"
+        );
+    }
+
+    #[test]
+    fn test_replace_item() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        let mut other: SmoothWeight<&str> = SmoothWeight::new();
+        for w in [&mut sw, &mut other].iter_mut() {
+            w.add("server1", 5);
+            w.add("server2", 2);
+            w.add("server3", 3);
+        }
+
+        for _ in 0..4 {
+            assert_eq!(sw.next(), other.next());
+        }
+
+        assert!(sw.replace_item(&"server2", "server4"));
+        assert!(!sw.replace_item(&"server2", "server5"));
+        for _ in 0..20 {
+            let expected = other
+                .next()
+                .map(|s| if s == "server2" { "server4" } else { s });
+            assert_eq!(sw.next(), expected);
+        }
+    }
+
+    #[test]
+    fn test_selection_floor() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 8);
+        sw.add("server2", 1);
+        sw.set_selection_floor(2);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 80);
+        assert_eq!(results["server2"], 20);
+
+        sw.set_selection_floor(0);
+        sw.reset();
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..90 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 80);
+        assert_eq!(results["server2"], 10);
+    }
+
+    #[test]
+    fn test_extreme_weights() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", isize::MAX);
+        sw.add("server2", isize::MAX);
+        sw.add("server3", 1);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(results["server1"], 50);
+        assert_eq!(results["server2"], 50);
+
+        assert_eq!(sw.remove(&"server1"), Some(("server1", isize::MAX)));
+        assert_eq!(sw.items.iter().map(|w| w.current_weight).sum::<i128>(), 0);
+    }
+
+    #[test]
+    fn test_update_weight() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+
+        for _ in 0..3 {
+            sw.next();
+        }
+
+        assert!(!sw.update_weight(&"server4", 1));
+        assert!(sw.update_weight(&"server1", 3));
+        assert_eq!(sw.items.iter().map(|w| w.current_weight).sum::<i128>(), 0);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..80 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        // the rescaled current weights may shift a single pick between items
+        assert!((29..=31).contains(&results["server1"]));
+        assert!((19..=21).contains(&results["server2"]));
+        assert!((29..=31).contains(&results["server3"]));
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        assert_eq!(sw.peek(), None);
+
+        sw.add("server1", 5);
+        sw.add("server2", 1);
+        sw.add("server3", 1);
+
+        for _ in 0..20 {
+            let peeked = sw.peek().copied();
+            assert_eq!(sw.peek().copied(), peeked);
+            assert_eq!(sw.next(), peeked);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut sw: SmoothWeight<String> = SmoothWeight::new();
+        sw.add("server1".to_string(), 5);
+        sw.add("server2".to_string(), 2);
+        sw.add("server3".to_string(), 3);
+        for _ in 0..4 {
+            sw.next();
+        }
+
+        let json = serde_json::to_string(&sw).unwrap();
+        let mut restored: SmoothWeight<String> = serde_json::from_str(&json).unwrap();
+        for (a, b) in sw.items.iter().zip(&restored.items) {
+            assert_eq!(a.weight, b.weight);
+            assert_eq!(a.current_weight, b.current_weight);
+            assert_eq!(a.effective_weight, b.effective_weight);
+        }
+        for _ in 0..20 {
+            assert_eq!(sw.next(), restored.next());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_older_formats() {
+        // fields added since are filled in with their defaults
+        let jsons = [
+            r#"{"items":[{"item":"a","weight":2,"current_weight":0,"effective_weight":2,"count":0},
+                {"item":"b","weight":1,"current_weight":0,"effective_weight":1,"count":0}],
+                "stats":false,"floor":0}"#,
+            r#"{"items":[{"item":"a","weight":2,"current_weight":0,"effective_weight":2},
+                {"item":"b","weight":1,"current_weight":0,"effective_weight":1}]}"#,
+        ];
+        for json in jsons.iter() {
+            let mut sw: SmoothWeight<String> = serde_json::from_str(json).unwrap();
+            assert_eq!(sw.limit(), None);
+            assert_eq!(sw.next_n(3), vec!["a", "b", "a"]);
+        }
+    }
+
+    #[test]
+    fn test_feedback() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 10);
+        sw.add("server2", 10);
+
+        let mut healthy = 0;
+        for _ in 0..100 {
+            if sw.next().unwrap() == "server1" {
+                healthy += 1;
+            }
+        }
+        assert_eq!(healthy, 50);
+
+        let mut failing = 0;
+        for _ in 0..100 {
+            if sw.next().unwrap() == "server1" {
+                failing += 1;
+                sw.feedback(&"server1", false);
+            } else {
+                sw.feedback(&"server2", true);
+            }
+        }
+        assert!(
+            failing < 30,
+            "failing server still picked {} times",
+            failing
+        );
+
+        // without further failures it recovers its full share
+        for _ in 0..20 {
+            sw.next();
+        }
+        let mut recovered = 0;
+        for _ in 0..100 {
+            if sw.next().unwrap() == "server1" {
+                recovered += 1;
+            }
+        }
+        assert!((45..=55).contains(&recovered));
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("a", 2);
+        sw.add("b", 1);
+        sw.next();
+
+        assert_eq!(
+            format!("{:?}", sw),
+            "SmoothWeight { items: {\"a\": Item { weight: 2, current_weight: -1, effective_weight: 2 }, \
+             \"b\": Item { weight: 1, current_weight: 1, effective_weight: 1 }} }"
+        );
+    }
+
+    #[test]
+    fn test_next_filtered() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..80 {
+            let s = sw.next_filtered(|s| *s != "server1").unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results.get("server1"), None);
+        assert_eq!(results["server2"], 32);
+        assert_eq!(results["server3"], 48);
+
+        // once the quarantine is lifted the full distribution comes back
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..100 {
+            *results.entry(sw.next().unwrap()).or_insert(0) += 1;
+        }
+        assert!((48..=52).contains(&results["server1"]));
+
+        assert_eq!(sw.next_filtered(|_| false), None);
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+        sw.add("server3", 3);
+        for _ in 0..7 {
+            sw.next();
+        }
+        sw.feedback(&"server1", false);
+
+        let mut restored = SmoothWeight::new();
+        restored.add("stale", 1);
+        restored.restore(sw.snapshot());
+
+        let expected: Vec<&str> = (0..20).map(|_| sw.next().unwrap()).collect();
+        let actual: Vec<&str> = (0..20).map(|_| restored.next().unwrap()).collect();
+        assert_eq!(actual, expected);
+
+        // caps survive the round trip
+        let mut capped: SmoothWeight<&str> = SmoothWeight::new();
+        capped.add_with_cap("server1", 5, 1);
+        capped.add("server2", 1);
+        let mut restored = SmoothWeight::new();
+        restored.restore(capped.snapshot());
+        assert_eq!(restored.acquire(), Some(&"server1"));
+        for _ in 0..5 {
+            assert_eq!(restored.acquire(), Some(&"server2"));
+        }
+    }
+
+    #[test]
+    fn test_update_weight_rescales() {
+        // when server1 drops to server2's weight, a current weight accumulated at the old rate
+        // would hand one of them a run of picks instead of alternating right away
+        for picks in 0..10 {
+            let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+            sw.add("server1", 9);
+            sw.add("server2", 1);
+            for _ in 0..picks {
+                sw.next();
+            }
+
+            sw.update_weight(&"server1", 1);
+            let next: Vec<&str> = sw.next_n(4);
+            let server1 = next.iter().filter(|s| **s == "server1").count();
+            assert!(
+                (1..=3).contains(&server1),
+                "{:?} after {} picks",
+                next,
+                picks
+            );
+        }
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut sw: SmoothWeight<usize> = SmoothWeight::new();
+        sw.reserve(100);
+        let capacity = sw.items.capacity();
+        assert!(capacity >= 100);
+        for i in 0..100 {
+            sw.add(i, 1);
+        }
+        assert_eq!(sw.items.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_clear_items() {
+        let mut sw: SmoothWeight<usize> = SmoothWeight::with_capacity(64);
+        for i in 0..64 {
+            sw.add(i, 1);
+        }
+        sw.next();
+
+        sw.clear_items();
+        assert!(sw.is_empty());
+        assert_eq!(sw.next(), None);
+        assert!(sw.items.capacity() >= 64);
+
+        sw.add(1, 1);
+        assert_eq!(sw.next(), Some(1));
+        sw.remove_all();
+        assert_eq!(sw.items.capacity(), 0);
+    }
+
+    #[test]
+    fn test_new_ordered() {
+        let mut a: SmoothWeight<&str> = SmoothWeight::new_ordered();
+        let mut b: SmoothWeight<&str> = SmoothWeight::new_ordered();
+        a.extend(vec![("server1", 2), ("server2", 3), ("server3", 2)]);
+        b.extend(vec![("server3", 2), ("server2", 3), ("server1", 2)]);
+        assert_eq!(a.next_n(14), b.next_n(14));
+
+        // plain balancers built the same way disagree
+        let mut c: SmoothWeight<&str> = vec![("server1", 2), ("server2", 3), ("server3", 2)]
+            .into_iter()
+            .collect();
+        let mut d: SmoothWeight<&str> = vec![("server3", 2), ("server2", 3), ("server1", 2)]
+            .into_iter()
+            .collect();
+        assert_ne!(c.next_n(7), d.next_n(7));
+
+        // the order follows weight changes and removals too
+        a.reset();
+        b.reset();
+        a.update_weight(&"server3", 4);
+        b.update_weight(&"server3", 4);
+        a.add("server4", 1);
+        b.add("server0", 1);
+        b.add("server4", 1);
+        b.remove(&"server0");
+        assert_eq!(a.next_n(10), b.next_n(10));
+        assert_eq!(a.get(0), Some((&"server3", 4)));
+    }
+
+    #[test]
+    fn test_reset_item() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 10);
+        sw.add("server2", 10);
+        sw.add("server3", 5);
+
+        sw.next();
+        sw.feedback(&"server2", false);
+        sw.next();
+        assert!(sw.reset_item(&"server2"));
+        assert!(!sw.reset_item(&"server4"));
+
+        let snapshot = sw.snapshot();
+        assert_eq!(snapshot[1].2, 0);
+        assert_eq!(snapshot[1].3, 10);
+        assert_eq!(snapshot.iter().map(|s| s.2).sum::<i128>(), 0);
+        assert_eq!(snapshot[0].3, 10);
+    }
+
+    #[test]
+    fn test_debug_state() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 1);
+        sw.add("server3", 1);
+
+        assert_eq!(sw.next(), Some("server1"));
+        sw.feedback(&"server2", false);
+        assert_eq!(
+            sw.debug_state(),
+            vec![
+                ("server1", 5, -2, 5),
+                ("server2", 1, 1, 1),
+                ("server3", 1, 1, 1)
+            ]
+        );
+
+        let mut big: SmoothWeight<&str> = SmoothWeight::new();
+        big.add("a", isize::MAX);
+        big.add("b", isize::MAX);
+        big.next();
+        assert_eq!(big.debug_state()[1].2, isize::MAX);
+    }
+
+    #[test]
+    fn test_swap_weights() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("blue", 9);
+        sw.add("green", 1);
+        sw.next_n(3);
+
+        assert!(sw.swap_weights(&"blue", &"green"));
+        assert!(!sw.swap_weights(&"blue", &"red"));
+        assert_eq!(sw.get(0), Some((&"blue", 1)));
+        let state = sw.debug_state();
+        assert_eq!(state.iter().map(|s| s.2).sum::<isize>(), 0);
+
+        let picks = sw.next_n(100);
+        assert_eq!(picks.iter().filter(|s| **s == "green").count(), 90);
+        assert_eq!(picks.iter().filter(|s| **s == "blue").count(), 10);
+    }
+
+    #[test]
+    fn test_acquire() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add_with_cap("server1", 5, 2);
+        sw.add_with_cap("server2", 1, 1);
+        sw.add("server3", 0);
+
+        let picks: Vec<&str> = (0..3).map(|_| *sw.acquire().unwrap()).collect();
+        assert_eq!(picks, vec!["server1", "server1", "server2"]);
+        assert_eq!(sw.in_flight(&"server1"), 2);
+        assert_eq!(sw.acquire(), None);
+        assert_eq!(sw.next(), None);
+        assert_eq!(sw.peek(), None);
+
+        sw.release(&"server2");
+        sw.release(&"server3");
+        assert_eq!(sw.peek(), Some(&"server2"));
+        assert_eq!(sw.acquire(), Some(&"server2"));
+        assert_eq!(sw.in_flight(&"server2"), 1);
+
+        // uncapped items are never full
+        sw.add("server4", 1);
+        for _ in 0..5 {
+            assert_eq!(sw.acquire(), Some(&"server4"));
+        }
+        sw.release(&"server1");
+        assert_eq!(sw.acquire(), Some(&"server1"));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 1);
+        sw.add("server3", 1);
+        sw.next_n(3);
+
+        // the copy carries on from the same point in the rotation
+        let mut copy = sw.clone();
+        assert_eq!(copy.next_n(4), sw.next_n(4));
+
+        copy.update_weight(&"server3", 0);
+        assert_eq!(sw.get(2), Some((&"server3", 1)));
+    }
 }
@@ -0,0 +1,259 @@
+use super::{Weight, WeightError};
+use std::time::Instant;
+
+/// A source of the current time, so throttling can be driven by a fake clock in tests.
+pub trait Clock {
+    /// returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// The default clock backed by `Instant::now()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// ThrottledWeight wraps any weighted balancer and caps how many items it hands out per second.
+///
+/// The limit is enforced with a token bucket holding at most `per_second` tokens, refilled
+/// continuously at `per_second` tokens per second. Every successful selection consumes one token
+/// and `next()` returns `None` while the bucket is empty, where `try_next()` returns
+/// `WeightError::Throttled`.
+#[derive(Debug)]
+pub struct ThrottledWeight<W, C = SystemClock> {
+    inner: W,
+    clock: C,
+    per_second: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl<W: Weight> ThrottledWeight<W> {
+    pub fn new(inner: W, per_second: u32) -> Self {
+        ThrottledWeight::with_clock(inner, per_second, SystemClock)
+    }
+}
+
+impl<W: Weight, C: Clock> ThrottledWeight<W, C> {
+    /// creates a throttled balancer that reads the time from `clock`.
+    pub fn with_clock(inner: W, per_second: u32, clock: C) -> Self {
+        let last = clock.now();
+        ThrottledWeight {
+            inner,
+            clock,
+            per_second: f64::from(per_second),
+            tokens: f64::from(per_second),
+            last,
+        }
+    }
+
+    /// returns the wrapped balancer.
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    /// unwraps the throttled balancer, returning the wrapped one.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn refill(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.per_second);
+    }
+
+    // refills the bucket and returns true if it doesn't hold a whole token
+    fn throttled(&mut self) -> bool {
+        self.refill();
+        self.tokens < 1.0
+    }
+}
+
+impl<W: Weight, C: Clock> Weight for ThrottledWeight<W, C> {
+    fn add(&mut self, item: Self::Item, weight: isize) {
+        self.inner.add(item, weight);
+    }
+
+    fn remove(&mut self, item: &Self::Item) -> Option<(Self::Item, isize)>
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.remove(item)
+    }
+
+    fn update_weight(&mut self, item: &Self::Item, weight: isize) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.update_weight(item, weight)
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.inner.all()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Self::Item, isize)> + '_ {
+        self.inner.iter()
+    }
+
+    fn remove_all(&mut self) {
+        self.inner.remove_all();
+    }
+
+    fn clear_items(&mut self) {
+        self.inner.clear_items();
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.inner.limit()
+    }
+
+    fn total_weight(&self) -> isize {
+        self.inner.total_weight()
+    }
+
+    fn min_weight(&self) -> Option<isize> {
+        self.inner.min_weight()
+    }
+
+    fn max_weight(&self) -> Option<isize> {
+        self.inner.max_weight()
+    }
+
+    fn get(&self, index: usize) -> Option<(&Self::Item, isize)> {
+        self.inner.get(index)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    fn contains(&self, item: &Self::Item) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.contains(item)
+    }
+
+    fn try_next(&mut self) -> Result<Self::Item, WeightError> {
+        if self.throttled() {
+            return Err(WeightError::Throttled);
+        }
+
+        let item = self.inner.try_next()?;
+        self.tokens -= 1.0;
+        Ok(item)
+    }
+
+    // the wrapped balancer skips rejected items itself, so the whole call costs one token
+    fn next_filtered<F: FnMut(&Self::Item) -> bool>(&mut self, pred: F) -> Option<Self::Item> {
+        if self.throttled() {
+            return None;
+        }
+
+        let item = self.inner.next_filtered(pred)?;
+        self.tokens -= 1.0;
+        Some(item)
+    }
+}
+
+impl<W: Weight, C: Clock> Iterator for ThrottledWeight<W, C> {
+    type Item = W::Item;
+
+    fn next(&mut self) -> Option<W::Item> {
+        if self.throttled() {
+            return None;
+        }
+
+        let item = self.inner.next()?;
+        self.tokens -= 1.0;
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Clock, SmoothWeight, ThrottledWeight, Weight, WeightError};
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    #[derive(Clone)]
+    struct ManualClock(Rc<Cell<Instant>>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_throttled_weight() {
+        let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 2);
+
+        let mut tw = ThrottledWeight::with_clock(sw, 2, clock.clone());
+        assert!(tw.next().is_some());
+        assert!(tw.next().is_some());
+        assert_eq!(tw.next(), None);
+
+        clock.0.set(clock.0.get() + Duration::from_millis(500));
+        assert!(tw.next().is_some());
+        assert_eq!(tw.next(), None);
+
+        // the bucket never holds more than one second worth of tokens
+        clock.0.set(clock.0.get() + Duration::from_secs(10));
+        assert!(tw.next().is_some());
+        assert!(tw.next().is_some());
+        assert_eq!(tw.next(), None);
+    }
+
+    #[test]
+    fn test_try_next_throttled() {
+        let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+        let mut tw = ThrottledWeight::with_clock(SmoothWeight::new(), 1, clock.clone());
+        assert_eq!(tw.try_next(), Err(WeightError::Empty));
+
+        tw.add("server1", 1);
+        assert_eq!(tw.try_next(), Ok("server1"));
+        assert_eq!(tw.try_next(), Err(WeightError::Throttled));
+
+        clock.0.set(clock.0.get() + Duration::from_secs(1));
+        tw.update_weight(&"server1", 0);
+        assert_eq!(tw.try_next(), Err(WeightError::ZeroTotalWeight));
+        tw.update_weight(&"server1", 1);
+        assert_eq!(tw.try_next(), Ok("server1"));
+    }
+
+    #[test]
+    fn test_next_filtered_takes_one_token() {
+        let clock = ManualClock(Rc::new(Cell::new(Instant::now())));
+        let mut sw: SmoothWeight<&str> = SmoothWeight::new();
+        sw.add("server1", 5);
+        sw.add("server2", 1);
+        sw.add("server3", 1);
+
+        let mut tw = ThrottledWeight::with_clock(sw, 2, clock);
+        assert_eq!(tw.next_excluding(&["server1", "server2"]), Some("server3"));
+        assert_eq!(tw.next_filtered(|s| *s == "server2"), Some("server2"));
+        assert_eq!(tw.next_filtered(|_| true), None);
+    }
+}
@@ -0,0 +1,244 @@
+use super::{rotation, Weight};
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug)]
+struct TieredWeightItem<T> {
+    item: T,
+    weight: isize,
+    tier: u32,
+    current_weight: i128,
+}
+
+/// TieredWeight implements strict-priority failover with weighted selection inside each tier.
+///
+/// Every item belongs to a tier, where tier 0 has the highest priority. A selection only ever
+/// considers the highest-priority tier that still has a healthy item with a positive weight, and
+/// picks among those items with the smooth weighted round-robin algorithm. Lower tiers receive no
+/// traffic at all until every item above them is unhealthy. Current weights are accumulated as
+/// `i128`, like in `SmoothWeight`, so weights up to `isize::MAX` can't overflow them.
+///
+/// Each tier keeps its current weights summing to zero: a removed item's current weight goes to
+/// another item of its tier, and an updated weight rescales the item's current weight and spreads
+/// the difference over its tier, as in `SmoothWeight`.
+#[derive(Debug, Default)]
+pub struct TieredWeight<T> {
+    items: Vec<TieredWeightItem<T>>,
+}
+
+impl<T: Clone> TieredWeight<T> {
+    pub const fn new() -> Self {
+        TieredWeight { items: Vec::new() }
+    }
+
+    /// adds a weighted item to the given tier.
+    pub fn add_tiered(&mut self, item: T, weight: isize, tier: u32) {
+        self.items.push(TieredWeightItem {
+            item,
+            weight,
+            tier,
+            current_weight: 0,
+        });
+    }
+
+    /// selects among the healthy items of the highest-priority tier that has any.
+    pub fn next_healthy<F: Fn(&T) -> bool>(&mut self, healthy: F) -> Option<T> {
+        let eligible: Vec<bool> = self
+            .items
+            .iter()
+            .map(|w| w.weight > 0 && healthy(&w.item))
+            .collect();
+        let tier = self
+            .items
+            .iter()
+            .zip(&eligible)
+            .filter(|(_, ok)| **ok)
+            .map(|(w, _)| w.tier)
+            .min()?;
+
+        let mut total = 0;
+        let mut best_index = 0;
+        let mut found = false;
+        for (i, ok) in eligible.into_iter().enumerate() {
+            if !ok || self.items[i].tier != tier {
+                continue;
+            }
+
+            self.items[i].current_weight += self.items[i].weight as i128;
+            total += self.items[i].weight as i128;
+            if !found || self.items[i].current_weight > self.items[best_index].current_weight {
+                found = true;
+                best_index = i;
+            }
+        }
+
+        self.items[best_index].current_weight -= total;
+        Some(self.items[best_index].item.clone())
+    }
+}
+
+impl<T: Clone> Weight for TieredWeight<T> {
+    /// adds a weighted item to tier 0.
+    fn add(&mut self, item: T, weight: isize) {
+        self.add_tiered(item, weight, 0);
+    }
+
+    fn remove(&mut self, item: &T) -> Option<(T, isize)>
+    where
+        T: PartialEq,
+    {
+        let index = self.items.iter().position(|w| w.item == *item)?;
+        let removed = self.items.remove(index);
+        let tier = removed.tier;
+        rotation::hand_off(&mut self.items, index, removed.current_weight, |w| {
+            (w.tier == tier).then_some((w.weight, &mut w.current_weight))
+        });
+        Some((removed.item, removed.weight))
+    }
+
+    fn update_weight(&mut self, item: &T, weight: isize) -> bool
+    where
+        T: PartialEq,
+    {
+        let w = match self.items.iter_mut().find(|w| w.item == *item) {
+            Some(w) => w,
+            None => return false,
+        };
+
+        let old_current = w.current_weight;
+        let new_current = rotation::rescale(old_current, w.weight, weight);
+        w.weight = weight;
+        w.current_weight = new_current;
+        let tier = w.tier;
+        rotation::redistribute(&mut self.items, old_current - new_current, |w| {
+            (w.tier == tier).then_some((w.weight, &mut w.current_weight))
+        });
+        true
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.items
+            .iter()
+            .map(|item| (item.item.clone(), item.weight))
+    }
+
+    fn remove_all(&mut self) {
+        self.clear_items();
+        self.items.shrink_to_fit();
+    }
+
+    fn clear_items(&mut self) {
+        self.items.clear();
+    }
+
+    fn reset(&mut self) {
+        for w in &mut self.items {
+            w.current_weight = 0;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(&T, isize)> {
+        self.items.get(index).map(|w| (&w.item, w.weight))
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional);
+    }
+
+    fn contains(&self, item: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.items.iter().any(|w| w.item == *item)
+    }
+}
+
+impl<T: Clone> Iterator for TieredWeight<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.next_healthy(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{TieredWeight, Weight};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_tiered_weight() {
+        let mut tw: TieredWeight<&str> = TieredWeight::new();
+        tw.add_tiered("primary1", 5, 0);
+        tw.add_tiered("primary2", 2, 0);
+        tw.add_tiered("secondary", 3, 1);
+        tw.add_tiered("tertiary", 3, 2);
+
+        let mut results: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..70 {
+            let s = tw.next().unwrap();
+            *results.entry(s).or_insert(0) += 1;
+        }
+        assert_eq!(results["primary1"], 50);
+        assert_eq!(results["primary2"], 20);
+        assert_eq!(results.get("secondary"), None);
+
+        for _ in 0..10 {
+            assert_eq!(tw.next_healthy(|s| *s != "primary1"), Some("primary2"));
+            assert_eq!(
+                tw.next_healthy(|s| !s.starts_with("primary")),
+                Some("secondary")
+            );
+            assert_eq!(tw.next_healthy(|s| *s == "tertiary"), Some("tertiary"));
+        }
+        assert_eq!(tw.next_healthy(|_| false), None);
+    }
+
+    #[test]
+    fn test_extreme_weights() {
+        let mut tw: TieredWeight<&str> = TieredWeight::new();
+        tw.add_tiered("primary1", isize::MAX, 0);
+        tw.add_tiered("primary2", isize::MAX, 0);
+        tw.add_tiered("secondary", isize::MAX, 1);
+
+        let picks: Vec<&str> = (0..4).map(|_| tw.next().unwrap()).collect();
+        assert_eq!(picks, vec!["primary1", "primary2", "primary1", "primary2"]);
+    }
+
+    #[test]
+    fn test_tier_current_weights_balance() {
+        let tier_sum = |tw: &TieredWeight<&str>, tier| -> i128 {
+            tw.items
+                .iter()
+                .filter(|w| w.tier == tier)
+                .map(|w| w.current_weight)
+                .sum()
+        };
+
+        let mut tw: TieredWeight<&str> = TieredWeight::new();
+        tw.add_tiered("primary1", 5, 0);
+        tw.add_tiered("primary2", 2, 0);
+        tw.add_tiered("primary3", 3, 0);
+        tw.add_tiered("secondary", 3, 1);
+        tw.next_n(4);
+        tw.next_healthy(|s| *s == "secondary");
+
+        tw.remove(&"primary1");
+        assert_eq!(tier_sum(&tw, 0), 0);
+        tw.update_weight(&"primary3", 9);
+        assert_eq!(tier_sum(&tw, 0), 0);
+        assert_eq!(tier_sum(&tw, 1), 0);
+
+        // the shares follow the weights right away, without a burst for either item
+        let picks = tw.next_n(11);
+        assert_eq!(picks.iter().filter(|s| **s == "primary3").count(), 9);
+
+        // once a tier has nothing selectable left, its rotation starts over
+        tw.update_weight(&"primary2", 0);
+        tw.remove(&"primary3");
+        assert!(tw.items.iter().all(|w| w.current_weight == 0));
+    }
+}
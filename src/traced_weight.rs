@@ -0,0 +1,197 @@
+use super::Weight;
+use alloc::collections::VecDeque;
+
+/// TracedWeight wraps any weighted balancer and remembers the last few items it selected, so
+/// operators can see which backends were chosen right before a latency spike or error burst.
+///
+/// The trace is a ring buffer whose capacity is fixed at construction; once full, each selection
+/// evicts the oldest entry. Balancers that are not wrapped pay nothing for it.
+#[derive(Debug)]
+pub struct TracedWeight<W: Weight> {
+    inner: W,
+    recent: VecDeque<W::Item>,
+    capacity: usize,
+}
+
+impl<W: Weight> TracedWeight<W>
+where
+    W::Item: Clone,
+{
+    /// wraps `inner`, keeping at most `capacity` recent selections.
+    pub fn new(inner: W, capacity: usize) -> Self {
+        TracedWeight {
+            inner,
+            recent: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// returns the recently selected items, oldest first.
+    pub fn recent_selections(&self) -> impl Iterator<Item = &W::Item> {
+        self.recent.iter()
+    }
+
+    /// forgets the recorded selections.
+    pub fn clear_trace(&mut self) {
+        self.recent.clear();
+    }
+
+    /// returns the wrapped balancer.
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    /// unwraps the traced balancer, returning the wrapped one.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn record(&mut self, item: &W::Item) {
+        if self.capacity > 0 {
+            if self.recent.len() == self.capacity {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(item.clone());
+        }
+    }
+}
+
+impl<W: Weight> Weight for TracedWeight<W>
+where
+    W::Item: Clone,
+{
+    fn add(&mut self, item: Self::Item, weight: isize) {
+        self.inner.add(item, weight);
+    }
+
+    fn remove(&mut self, item: &Self::Item) -> Option<(Self::Item, isize)>
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.remove(item)
+    }
+
+    fn update_weight(&mut self, item: &Self::Item, weight: isize) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.update_weight(item, weight)
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Self::Item, isize)> + '_ {
+        self.inner.all()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Self::Item, isize)> + '_ {
+        self.inner.iter()
+    }
+
+    fn remove_all(&mut self) {
+        self.inner.remove_all();
+    }
+
+    fn clear_items(&mut self) {
+        self.inner.clear_items();
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.inner.limit()
+    }
+
+    fn total_weight(&self) -> isize {
+        self.inner.total_weight()
+    }
+
+    fn min_weight(&self) -> Option<isize> {
+        self.inner.min_weight()
+    }
+
+    fn max_weight(&self) -> Option<isize> {
+        self.inner.max_weight()
+    }
+
+    fn get(&self, index: usize) -> Option<(&Self::Item, isize)> {
+        self.inner.get(index)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    fn contains(&self, item: &Self::Item) -> bool
+    where
+        Self::Item: PartialEq,
+    {
+        self.inner.contains(item)
+    }
+
+    // only the item handed out goes into the trace, not the ones the filter turned down
+    fn next_filtered<F: FnMut(&Self::Item) -> bool>(&mut self, pred: F) -> Option<Self::Item> {
+        let item = self.inner.next_filtered(pred)?;
+        self.record(&item);
+        Some(item)
+    }
+}
+
+impl<W: Weight> Iterator for TracedWeight<W>
+where
+    W::Item: Clone,
+{
+    type Item = W::Item;
+
+    fn next(&mut self) -> Option<W::Item> {
+        let item = self.inner.next()?;
+        self.record(&item);
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RoundrobinWeight, TracedWeight, Weight};
+
+    #[test]
+    fn test_recent_selections() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 1);
+        rrw.add("server2", 1);
+        rrw.add("server3", 1);
+
+        let mut tw = TracedWeight::new(rrw, 2);
+        assert_eq!(tw.recent_selections().count(), 0);
+
+        tw.next();
+        tw.next();
+        tw.next();
+        assert_eq!(
+            tw.recent_selections().copied().collect::<Vec<_>>(),
+            vec!["server2", "server3"]
+        );
+
+        tw.clear_trace();
+        assert_eq!(tw.recent_selections().count(), 0);
+    }
+
+    #[test]
+    fn test_next_filtered_traces_selection() {
+        let mut rrw: RoundrobinWeight<&str> = RoundrobinWeight::new();
+        rrw.add("server1", 1);
+        rrw.add("server2", 1);
+        rrw.add("server3", 1);
+
+        let mut tw = TracedWeight::new(rrw, 3);
+        assert_eq!(tw.next_excluding(&["server1", "server2"]), Some("server3"));
+        assert_eq!(
+            tw.recent_selections().copied().collect::<Vec<_>>(),
+            vec!["server3"]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use super::{AsyncWeight, Weight};
+use std::fmt;
+use std::future::{self, Future};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::watch;
+
+struct Watched<W: Weight> {
+    balancer: W,
+    rx: watch::Receiver<Vec<(W::Item, isize)>>,
+}
+
+impl<W: Weight> Watched<W>
+where
+    W::Item: Clone,
+{
+    // swaps in the latest published items; the caller holds the lock throughout, so no selection
+    // can see a half-rebuilt balancer
+    fn apply(&mut self) {
+        self.balancer.clear_items();
+        for (item, weight) in self.rx.borrow_and_update().iter() {
+            self.balancer.add(item.clone(), *weight);
+        }
+    }
+}
+
+/// WatchedWeight keeps a balancer in sync with the items and weights published on a
+/// `tokio::sync::watch` channel, e.g. by a service discovery task.
+///
+/// Every published value is the complete list of `(item, weight)` pairs. It replaces the
+/// balancer's items as a whole, so the rotation restarts with each update. Updates are picked up
+/// by the first `next()` after they are published, or as soon as they arrive by awaiting
+/// `changed()` in a task of its own. Either way the rebuild happens under the same lock as
+/// selection, so `next()` never sees a half-updated item set.
+///
+/// Cloning a `WatchedWeight` is cheap and every clone drives the same balancer. The lock is a
+/// `std` mutex that is never held across an `.await`, so `next()` can be called from async code
+/// directly. A poisoned lock is recovered rather than propagated, like in `SharedWeight`.
+///
+/// It also implements `AsyncWeight`, for code that is generic over async balancers.
+pub struct WatchedWeight<W: Weight> {
+    inner: Arc<Mutex<Watched<W>>>,
+}
+
+impl<W: Weight> Clone for WatchedWeight<W> {
+    fn clone(&self) -> Self {
+        WatchedWeight {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<W: Weight> WatchedWeight<W>
+where
+    W::Item: Clone,
+{
+    /// wraps `balancer`, replacing its items with the value currently in `rx`.
+    pub fn new(balancer: W, rx: watch::Receiver<Vec<(W::Item, isize)>>) -> Self {
+        let mut watched = Watched { balancer, rx };
+        watched.apply();
+        WatchedWeight {
+            inner: Arc::new(Mutex::new(watched)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Watched<W>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// selects the next item, first applying an update if one was published since the last
+    /// call.
+    pub fn next(&self) -> Option<W::Item> {
+        let mut watched = self.lock();
+        if watched.rx.has_changed().unwrap_or(false) {
+            watched.apply();
+        }
+        watched.balancer.next()
+    }
+
+    /// waits for the next published update and applies it. Fails once the sender is dropped, after
+    /// which the balancer keeps the last items it got.
+    pub async fn changed(&self) -> Result<(), watch::error::RecvError> {
+        // wait on a clone of the receiver so the lock isn't held across the await
+        let mut rx = self.lock().rx.clone();
+        rx.changed().await?;
+        self.lock().apply();
+        Ok(())
+    }
+
+    /// runs `f` with the balancer locked, for anything not covered by the methods above. Changes
+    /// made here are overwritten by the next update.
+    pub fn with_inner<U>(&self, f: impl FnOnce(&mut W) -> U) -> U {
+        f(&mut self.lock().balancer)
+    }
+}
+
+impl<W> AsyncWeight for WatchedWeight<W>
+where
+    W: Weight + Send,
+    W::Item: Clone + Send + Sync,
+{
+    type Item = W::Item;
+
+    fn next(&self) -> impl Future<Output = Option<W::Item>> + Send {
+        // selection never waits, only the lock is taken
+        future::ready(WatchedWeight::next(self))
+    }
+
+    async fn changed(&self) -> bool {
+        WatchedWeight::changed(self).await.is_ok()
+    }
+}
+
+impl<W: Weight + fmt::Debug> fmt::Debug for WatchedWeight<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let watched = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("WatchedWeight")
+            .field("balancer", &watched.balancer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AsyncWeight, RoundrobinWeight, SmoothWeight, WatchedWeight, Weight};
+    use tokio::sync::watch;
+
+    #[test]
+    fn test_watched_weight() {
+        let (tx, rx) = watch::channel(vec![("server1", 1), ("server2", 1)]);
+        let ww = WatchedWeight::new(RoundrobinWeight::new(), rx);
+        assert_eq!(ww.next(), Some("server1"));
+        assert_eq!(ww.next(), Some("server2"));
+
+        tx.send(vec![("server3", 2), ("server4", 1)]).unwrap();
+        let picks: Vec<_> = (0..3).map(|_| ww.clone().next().unwrap()).collect();
+        assert_eq!(picks, vec!["server3", "server3", "server4"]);
+        assert_eq!(ww.with_inner(|w| w.len()), 2);
+
+        tx.send(vec![]).unwrap();
+        assert_eq!(ww.next(), None);
+    }
+
+    #[tokio::test]
+    async fn test_changed() {
+        let (tx, rx) = watch::channel(vec![("server1", 1)]);
+        let ww = WatchedWeight::new(SmoothWeight::new(), rx);
+
+        let updater = {
+            let ww = ww.clone();
+            tokio::spawn(async move { while ww.changed().await.is_ok() {} })
+        };
+
+        tx.send(vec![("server2", 1)]).unwrap();
+        tokio::task::yield_now().await;
+        assert!(ww.with_inner(|w| w.contains(&"server2")));
+        assert_eq!(ww.next(), Some("server2"));
+
+        // the balancer keeps its last items once the sender goes away
+        drop(tx);
+        updater.await.unwrap();
+        assert_eq!(ww.next(), Some("server2"));
+    }
+
+    #[tokio::test]
+    async fn test_async_weight() {
+        async fn pick<A: AsyncWeight>(w: &A) -> Option<A::Item> {
+            w.next().await
+        }
+
+        let (tx, rx) = watch::channel(vec![("server1", 1)]);
+        let ww = WatchedWeight::new(SmoothWeight::new(), rx);
+        assert_eq!(pick(&ww).await, Some("server1"));
+
+        let updater = {
+            let ww = ww.clone();
+            tokio::spawn(async move { AsyncWeight::changed(&ww).await })
+        };
+        tokio::task::yield_now().await;
+        tx.send(vec![("server2", 1)]).unwrap();
+        assert!(updater.await.unwrap());
+        assert_eq!(pick(&ww).await, Some("server2"));
+
+        drop(tx);
+        assert!(!AsyncWeight::changed(&ww).await);
+    }
+}
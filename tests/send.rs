@@ -0,0 +1,24 @@
+#![cfg(feature = "random")]
+
+use rand::{rngs::StdRng, SeedableRng};
+use std::thread;
+use weighted_rs::{RandWeight, Weight};
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn rand_weight_with_std_rng_is_send() {
+    assert_send::<RandWeight<&str, StdRng>>();
+
+    let mut rw = RandWeight::with_rng(StdRng::seed_from_u64(7));
+    rw.add("server1", 5);
+    rw.add("server2", 2);
+
+    // a clone keeps the generator state, so both copies draw the same sequence
+    let mut copy = rw.clone();
+    let local: Vec<_> = (0..20).map(|_| copy.next().unwrap()).collect();
+    let moved = thread::spawn(move || (0..20).map(|_| rw.next().unwrap()).collect::<Vec<_>>())
+        .join()
+        .unwrap();
+    assert_eq!(local, moved);
+}